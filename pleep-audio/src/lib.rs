@@ -1,17 +1,20 @@
-use std::{collections::VecDeque, path::PathBuf};
+use std::{collections::VecDeque, path::PathBuf, time::Duration};
 
 use rubato::Resampler;
 use symphonia::core::{
+    audio::Signal,
     codecs::{Decoder, DecoderOptions},
     conv::FromSample,
-    formats::{FormatOptions, FormatReader},
+    errors::Error as SymphoniaError,
+    formats::{FormatOptions, FormatReader, Packet, SeekMode, SeekTo, Track},
     io::{MediaSourceStream, MediaSourceStreamOptions},
     meta::MetadataOptions,
     probe::Hint,
     sample::Sample,
+    units::{Time, TimeBase},
 };
 use thiserror::Error;
-use tracing::{error, instrument};
+use tracing::{debug, error, instrument, warn};
 
 pub trait AnySample:
     Sample
@@ -47,6 +50,24 @@ impl<
 pub trait ExtendedAnySample: AnySample + rubato::Sample {}
 impl<T: AnySample + rubato::Sample> ExtendedAnySample for T {}
 
+/// Codecs and containers that can be decoded, as picked by cargo features
+pub fn compiled_formats() -> Vec<&'static str> {
+    [
+        ("mp3", cfg!(feature = "mp3")),
+        ("flac", cfg!(feature = "flac")),
+        ("wav", cfg!(feature = "wav")),
+        ("vorbis", cfg!(feature = "vorbis")),
+        ("aac", cfg!(feature = "aac")),
+        ("isomp4", cfg!(feature = "isomp4")),
+        ("mkv", cfg!(feature = "mkv")),
+        ("pcm", cfg!(feature = "pcm")),
+        ("adpcm", cfg!(feature = "adpcm")),
+    ]
+    .into_iter()
+    .filter_map(|(format, enabled)| enabled.then_some(format))
+    .collect()
+}
+
 pub struct AudioSource {
     media_source: MediaSourceStream,
 }
@@ -78,6 +99,85 @@ impl AudioSource {
             media_source: media_source_stream,
         }
     }
+
+    /// Lists every track in the container, in the order [`TrackSelection::Index`] uses
+    pub fn tracks(self) -> Result<Vec<TrackInfo>, Error> {
+        Ok(self.probe()?.tracks().iter().map(TrackInfo::new).collect())
+    }
+
+    fn probe(self) -> Result<Box<dyn FormatReader>, Error> {
+        let probed = symphonia::default::get_probe().format(
+            &Hint::new(),
+            self.media_source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+
+        Ok(probed.format)
+    }
+}
+
+/// Summary of a track in a container, as listed by [`AudioSource::tracks`]
+#[derive(Debug, Clone)]
+pub struct TrackInfo {
+    /// Identifier used by the container, not necessarily the same as the track's index
+    pub id: u32,
+    /// Short name of the codec, `None` if it can't be decoded
+    pub codec: Option<&'static str>,
+    pub channels: Option<usize>,
+    pub sample_rate: Option<u32>,
+    /// Language code as written in the container, e.g. `eng`
+    pub language: Option<String>,
+}
+
+impl TrackInfo {
+    fn new(track: &Track) -> Self {
+        Self {
+            id: track.id,
+            codec: symphonia::default::get_codecs()
+                .get_codec(track.codec_params.codec)
+                .map(|descriptor| descriptor.short_name),
+            channels: track.codec_params.channels.map(|channels| channels.count()),
+            sample_rate: track.codec_params.sample_rate,
+            language: track.language.clone(),
+        }
+    }
+}
+
+/// Which track of a container is decoded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TrackSelection {
+    /// The track the container marks as the default, usually the first one
+    #[default]
+    Default,
+    /// The track at this index of [`AudioSource::tracks`], starting from zero
+    Index(usize),
+    /// The first track in this language that can be decoded
+    Language(String),
+}
+
+fn select_track<'a>(
+    format: &'a dyn FormatReader,
+    selection: &TrackSelection,
+) -> Result<&'a Track, Error> {
+    let tracks = format.tracks();
+
+    match selection {
+        TrackSelection::Default => format.default_track().ok_or(Error::NoDefaultTrack),
+        TrackSelection::Index(index) => tracks.get(*index).ok_or(Error::NoTrackAtIndex {
+            index: *index,
+            tracks: tracks.len(),
+        }),
+        TrackSelection::Language(language) => tracks
+            .iter()
+            .find(|track| {
+                track.language.as_ref() == Some(language)
+                    && symphonia::default::get_codecs()
+                        .get_codec(track.codec_params.codec)
+                        .is_some()
+            })
+            .ok_or_else(|| Error::NoTrackWithLanguage(language.to_owned())),
+    }
 }
 
 #[derive(Debug)]
@@ -86,46 +186,354 @@ pub struct Audio<T: AnySample> {
     pub sample_rate: usize,
 }
 
+/// How the channels of multi-channel audio are turned into the single channel that is processed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChannelMode {
+    /// The mean of every channel
+    #[default]
+    Average,
+    /// Only the channel with this index, starting from zero
+    Channel(usize),
+    /// The mean of the first two channels, or the only channel of mono audio
+    Mid,
+    /// Half the difference between the first two channels, silent for mono audio
+    Side,
+    /// Whichever channel has had the most energy over the last [`MAX_ENERGY_WINDOW`], only switching
+    /// channels once another one has [`MAX_ENERGY_HYSTERESIS`] times as much
+    MaxEnergy,
+}
+
+/// Length of audio that [`ChannelMode::MaxEnergy`] compares the energy of each channel over
+pub const MAX_ENERGY_WINDOW: Duration = Duration::from_secs(10);
+/// How many times more energy than the current channel another channel needs before
+/// [`ChannelMode::MaxEnergy`] switches to it
+pub const MAX_ENERGY_HYSTERESIS: f64 = 2.0;
+
+/// The channel that [`ChannelMode::MaxEnergy`] is currently following
+#[derive(Debug, Clone)]
+struct LoudestChannel<T> {
+    /// Energy of each channel, with each sample's contribution decaying over [`MAX_ENERGY_WINDOW`]
+    energies: Vec<T>,
+    /// Samples per channel it takes for energy to decay by a factor of e
+    window_len: f64,
+    channel: Option<usize>,
+}
+
+impl<T: ExtendedAnySample> LoudestChannel<T> {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            energies: Vec::new(),
+            window_len: MAX_ENERGY_WINDOW.as_secs_f64() * sample_rate as f64,
+            channel: None,
+        }
+    }
+
+    /// Adds the energy of `planes`, one slice per channel, and returns the channel to follow for them
+    fn update(&mut self, planes: &[&[T]]) -> usize {
+        self.energies.resize(planes.len(), T::zero());
+
+        let len = planes.first().map_or(0, |plane| plane.len());
+        let decay = T::coerce((-(len as f64) / self.window_len).exp());
+        for (energy, plane) in self.energies.iter_mut().zip(planes) {
+            *energy = plane
+                .iter()
+                .fold(*energy * decay, |total, v| total + *v * *v);
+        }
+
+        let loudest = (0..self.energies.len())
+            .max_by(|l, r| {
+                self.energies[*l]
+                    .partial_cmp(&self.energies[*r])
+                    .unwrap_or(std::cmp::Ordering::Less)
+            })
+            .unwrap_or(0);
+        let hysteresis = T::coerce(MAX_ENERGY_HYSTERESIS);
+        let channel = match self.channel {
+            Some(channel)
+                if channel < self.energies.len()
+                    && self.energies[loudest] <= self.energies[channel] * hysteresis =>
+            {
+                channel
+            }
+            _ => loudest,
+        };
+
+        self.channel = Some(channel);
+        channel
+    }
+}
+
+impl ChannelMode {
+    /// Appends the samples of `planes`, one slice per channel, to `out` as a single channel
+    fn mix_into<T: ExtendedAnySample>(
+        self,
+        planes: &[&[T]],
+        loudest: &mut LoudestChannel<T>,
+        out: &mut VecDeque<T>,
+    ) -> Result<(), Error> {
+        let Some(first) = planes.first() else {
+            return Ok(());
+        };
+
+        match self {
+            ChannelMode::Average => {
+                let scale = T::coerce(1.0 / planes.len() as f64);
+                out.extend((0..first.len()).map(|index| {
+                    planes
+                        .iter()
+                        .fold(T::zero(), |total, plane| total + plane[index])
+                        * scale
+                }));
+            }
+            ChannelMode::Channel(channel) => {
+                let plane = planes.get(channel).ok_or(Error::MissingChannel {
+                    channel,
+                    channels: planes.len(),
+                })?;
+                out.extend(plane.iter());
+            }
+            ChannelMode::Mid if planes.len() < 2 => out.extend(first.iter()),
+            ChannelMode::Side if planes.len() < 2 => {
+                out.extend(std::iter::repeat_n(T::zero(), first.len()))
+            }
+            ChannelMode::Mid => {
+                let half = T::coerce(0.5);
+                out.extend(
+                    planes[0]
+                        .iter()
+                        .zip(planes[1])
+                        .map(|(l, r)| (*l + *r) * half),
+                );
+            }
+            ChannelMode::Side => {
+                let half = T::coerce(0.5);
+                out.extend(
+                    planes[0]
+                        .iter()
+                        .zip(planes[1])
+                        .map(|(l, r)| (*l - *r) * half),
+                );
+            }
+            ChannelMode::MaxEnergy => out.extend(planes[loudest.update(planes)].iter()),
+        }
+
+        Ok(())
+    }
+}
+
+/// Part of a track, measured from its start
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: Duration,
+    /// `None` to decode until the end of the track
+    pub end: Option<Duration>,
+}
+
+/// How much of a track was lost to errors while decoding it
+#[derive(Debug, Clone, Default)]
+pub struct DecodeHealth {
+    /// Packets that couldn't be decoded and were skipped
+    pub packets_skipped: usize,
+    /// Samples per channel held by the skipped packets, going by the durations the container gives them
+    pub samples_lost: u64,
+    /// Times the decoder had to be rebuilt because the stream changed
+    pub resets: usize,
+    /// Decoding stopped before the end of the track, see [`ConvertingAudioIterator::error`]
+    pub ended_early: bool,
+}
+
+impl DecodeHealth {
+    /// Nothing was skipped and the whole track was decoded
+    pub fn is_clean(&self) -> bool {
+        self.packets_skipped == 0 && !self.ended_early
+    }
+}
+
 pub struct ConvertingAudioIterator<T: ExtendedAnySample> {
     format: Box<dyn FormatReader>,
     decoder: Box<dyn Decoder>,
     discovered_sample_rate: u32,
     track_id: u32,
+    channel_mode: ChannelMode,
+    loudest: LoudestChannel<T>,
+    time_base: TimeBase,
+    time_range: TimeRange,
+    /// The end of the track, or of the time range, has been reached
+    ended: bool,
     buffer: VecDeque<T>,
+    health: DecodeHealth,
+    /// The error that ended decoding early, no more packets are read once this is set
+    error: Option<Error>,
 }
 
 impl<T: ExtendedAnySample> ConvertingAudioIterator<T> {
-    pub fn new(
-        AudioSource { media_source }: AudioSource,
-    ) -> Result<Self, symphonia::core::errors::Error> {
-        let registry = symphonia::default::get_codecs();
-        let probe = symphonia::default::get_probe();
-        let format = probe.format(
-            &Hint::new(),
-            media_source,
-            &FormatOptions::default(),
-            &MetadataOptions::default(),
-        )?;
+    /// Decodes the container's default track
+    pub fn new(source: AudioSource) -> Result<Self, Error> {
+        Self::new_with_track(source, &TrackSelection::Default)
+    }
+
+    pub fn new_with_track(source: AudioSource, selection: &TrackSelection) -> Result<Self, Error> {
+        let format = source.probe()?;
 
-        let default_track = format.format.default_track().expect("no default track");
-        let default_track_id = default_track.id;
-        let default_track_params = default_track.codec_params.clone();
+        let track = select_track(format.as_ref(), selection)?;
+        let track_id = track.id;
+        let track_params = track.codec_params.clone();
 
-        let decoder = registry.make(&default_track_params, &DecoderOptions::default())?;
+        let sample_rate = track_params
+            .sample_rate
+            .ok_or(Error::NoSampleRate { track_id })?;
+        let decoder =
+            symphonia::default::get_codecs().make(&track_params, &DecoderOptions::default())?;
 
         Ok(Self {
-            discovered_sample_rate: default_track_params.sample_rate.unwrap(),
-            format: format.format,
+            discovered_sample_rate: sample_rate,
+            format,
             decoder,
-            track_id: default_track_id,
+            track_id,
+            channel_mode: ChannelMode::default(),
+            loudest: LoudestChannel::new(sample_rate),
+            time_base: track_params
+                .time_base
+                .unwrap_or(TimeBase::new(1, sample_rate)),
+            time_range: TimeRange::default(),
+            ended: false,
             buffer: VecDeque::new(),
+            health: DecodeHealth::default(),
+            error: None,
         })
     }
 
+    pub fn with_channel_mode(mut self, channel_mode: ChannelMode) -> Self {
+        self.channel_mode = channel_mode;
+        self
+    }
+
+    pub fn channel_mode(&self) -> ChannelMode {
+        self.channel_mode
+    }
+
+    /// Only decodes part of the track, must be called before any samples are read.
+    ///
+    /// Seeks to the start where the container supports it, and otherwise decodes from the beginning of
+    /// the track. Either way, samples outside of the range are trimmed off.
+    pub fn with_time_range(mut self, time_range: TimeRange) -> Result<Self, Error> {
+        if let Some(end) = time_range.end.filter(|end| *end <= time_range.start) {
+            return Err(Error::InvalidTimeRange {
+                start: time_range.start,
+                end,
+            });
+        }
+
+        self.time_range = time_range;
+        if time_range.start.is_zero() {
+            return Ok(self);
+        }
+
+        let seeked = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: time_range.start.into(),
+                track_id: Some(self.track_id),
+            },
+        );
+
+        match seeked {
+            Ok(seeked_to) => {
+                debug!(?seeked_to, "seeked to start");
+                self.decoder.reset();
+            }
+            Err(error) => debug!(?error, "failed to seek, decoding from the start"),
+        }
+
+        Ok(self)
+    }
+
+    pub fn time_range(&self) -> TimeRange {
+        self.time_range
+    }
+
     pub fn sample_rate(&self) -> u32 {
         self.discovered_sample_rate
     }
 
+    /// Errors recovered from so far
+    pub fn health(&self) -> &DecodeHealth {
+        &self.health
+    }
+
+    /// The error that stopped decoding before the end of the track, if there was one
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    /// Like [`Self::error`], but hands over the error
+    pub fn into_error(self) -> Option<Error> {
+        self.error
+    }
+
+    /// Rebuilds the decoder after the container asks for it, which can happen when a stream is chained
+    /// onto another
+    fn reset(&mut self) -> Result<(), Error> {
+        let tracks = self.format.tracks();
+        let track = tracks
+            .iter()
+            .find(|track| track.id == self.track_id)
+            .or_else(|| self.format.default_track())
+            .ok_or(Error::NoDefaultTrack)?;
+
+        if track.codec_params.sample_rate != Some(self.discovered_sample_rate) {
+            warn!(
+                sample_rate = ?track.codec_params.sample_rate,
+                expected = self.discovered_sample_rate,
+                "sample rate changed after reset, audio will play at the wrong speed"
+            );
+        }
+
+        self.track_id = track.id;
+        if let Some(time_base) = track.codec_params.time_base {
+            self.time_base = time_base;
+        }
+        self.decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+        self.health.resets += 1;
+
+        Ok(())
+    }
+
+    /// Index of the frame at `time`, at the track's sample rate
+    fn frame_at(&self, time: Time) -> u64 {
+        let sample_rate = self.discovered_sample_rate as u64;
+
+        time.seconds * sample_rate + (time.frac * sample_rate as f64).round() as u64
+    }
+
+    fn start_frame(&self) -> u64 {
+        self.frame_at(self.time_range.start.into())
+    }
+
+    fn end_frame(&self) -> Option<u64> {
+        self.time_range.end.map(|end| self.frame_at(end.into()))
+    }
+
+    fn skip(&mut self, packet: &Packet, error: SymphoniaError) {
+        self.health.packets_skipped += 1;
+        self.health.samples_lost += packet.dur();
+
+        warn!(
+            ?error,
+            timestamp = packet.ts(),
+            packets_skipped = self.health.packets_skipped,
+            "skipping packet that failed to decode"
+        );
+    }
+
+    fn stop(&mut self, error: Error) {
+        error!(?error, "stopped decoding before the end of the track");
+
+        self.health.ended_early = true;
+        self.error = Some(error);
+    }
+
     pub fn remaining_to_audio(self) -> Audio<T> {
         let sample_rate = self.sample_rate() as usize;
         let samples = self.collect::<Vec<_>>();
@@ -145,16 +553,60 @@ impl<T: ExtendedAnySample> Iterator for ConvertingAudioIterator<T> {
             return Some(sample);
         }
 
-        while let Ok(packet) = self.format.next_packet() {
+        while self.error.is_none() && !self.ended {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                // symphonia signals the end of the stream this way
+                Err(SymphoniaError::IoError(error))
+                    if error.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    self.ended = true;
+                    continue;
+                }
+                Err(SymphoniaError::ResetRequired) => {
+                    if let Err(error) = self.reset() {
+                        self.stop(error);
+                    }
+                    continue;
+                }
+                Err(error) => {
+                    self.stop(error.into());
+                    continue;
+                }
+            };
+
             if packet.track_id() != self.track_id {
                 continue;
             }
 
+            let first_frame = self.frame_at(self.time_base.calc_time(packet.ts()));
+            if let Some(end) = self.end_frame() {
+                if first_frame >= end {
+                    self.ended = true;
+                    continue;
+                }
+            }
+
             let audio_buffer = match self.decoder.decode(&packet) {
-                Ok(packet) => packet,
+                Ok(audio_buffer) => audio_buffer,
+                // the packet is corrupt or cut short, but the ones after it are likely fine. decoders
+                // read from the packet's own buffer, so an io error here means they ran off the end
+                // of a truncated packet rather than that the file can't be read, which would have
+                // come from `next_packet` instead
+                Err(error @ (SymphoniaError::DecodeError(_) | SymphoniaError::IoError(_))) => {
+                    self.skip(&packet, error);
+                    continue;
+                }
+                Err(SymphoniaError::ResetRequired) => {
+                    self.skip(&packet, SymphoniaError::ResetRequired);
+                    if let Err(error) = self.reset() {
+                        self.stop(error);
+                    }
+                    continue;
+                }
                 Err(error) => {
-                    error!(?error, "failed to decode packet");
-                    return None;
+                    self.stop(error.into());
+                    continue;
                 }
             };
 
@@ -162,13 +614,34 @@ impl<T: ExtendedAnySample> Iterator for ConvertingAudioIterator<T> {
             audio_buffer.convert(&mut float_converted);
             drop(audio_buffer);
 
-            let planes = float_converted.planes();
-            let planes_slice = planes.planes();
-            let main_channel = planes_slice[0];
+            // frames before the start are left over from seeking to a point before it, or from
+            // decoding everything when seeking isn't supported
+            let frames = float_converted.frames() as u64;
+            let start = self.start_frame().saturating_sub(first_frame).min(frames);
+            let end = self
+                .end_frame()
+                .map_or(frames, |end| (end - first_frame).min(frames));
+            if end < frames {
+                self.ended = true;
+            }
 
-            self.buffer.extend(main_channel);
+            let planes = float_converted.planes();
+            let trimmed = planes
+                .planes()
+                .iter()
+                .map(|plane| &plane[start as usize..end as usize])
+                .collect::<Vec<_>>();
+            if let Err(error) =
+                self.channel_mode
+                    .mix_into(&trimmed, &mut self.loudest, &mut self.buffer)
+            {
+                self.stop(error);
+                continue;
+            }
 
-            return self.buffer.pop_front();
+            if let Some(sample) = self.buffer.pop_front() {
+                return Some(sample);
+            }
         }
 
         None
@@ -176,7 +649,8 @@ impl<T: ExtendedAnySample> Iterator for ConvertingAudioIterator<T> {
 }
 
 pub struct ResamplingChunksIterator<T: ExtendedAnySample, I: Iterator<Item = T>> {
-    inner_iterator: I,
+    /// Fused so the final partial chunk is the last one, even if `I` would carry on after ending
+    inner_iterator: std::iter::Fuse<I>,
     resampler: rubato::FftFixedIn<T>,
     settings: ResampleSettings,
 }
@@ -196,7 +670,7 @@ impl<T: ExtendedAnySample, I: Iterator<Item = T>> ResamplingChunksIterator<T, I>
         )?;
 
         Ok(Self {
-            inner_iterator: wraps,
+            inner_iterator: wraps.fuse(),
             resampler,
             settings,
         })
@@ -270,8 +744,318 @@ pub enum Error {
     Symphonia(#[from] symphonia::core::errors::Error),
     #[error("audio did not have a default track")]
     NoDefaultTrack,
+    #[error("no track at index {index}, audio has {tracks} tracks")]
+    NoTrackAtIndex { index: usize, tracks: usize },
+    #[error("no track that can be decoded has the language {0:?}")]
+    NoTrackWithLanguage(String),
+    #[error("track {track_id} did not have a sample rate")]
+    NoSampleRate { track_id: u32 },
+    #[error("channel {channel} does not exist, audio has {channels} channels")]
+    MissingChannel { channel: usize, channels: usize },
+    #[error("time range ends at {end:?}, which is not after its start at {start:?}")]
+    InvalidTimeRange { start: Duration, end: Duration },
     #[error("error constructing resampler: {0:?}")]
     ResamplerConstruction(#[from] rubato::ResamplerConstructionError),
     #[error("error resampling: {0:?}")]
     Resampler(#[from] rubato::ResampleError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A wav file holding `data` as its only chunk after `fmt `
+    fn wav(
+        format: u16,
+        channels: u16,
+        block_align: u16,
+        bits: u16,
+        extra: &[u8],
+        data: &[u8],
+    ) -> Vec<u8> {
+        const SAMPLE_RATE: u32 = 8000;
+
+        let mut fmt = Vec::new();
+        fmt.extend(format.to_le_bytes());
+        fmt.extend(channels.to_le_bytes());
+        fmt.extend(SAMPLE_RATE.to_le_bytes());
+        fmt.extend((SAMPLE_RATE * block_align as u32).to_le_bytes());
+        fmt.extend(block_align.to_le_bytes());
+        fmt.extend(bits.to_le_bytes());
+        fmt.extend(extra);
+
+        let mut file = Vec::new();
+        file.extend(b"RIFF");
+        file.extend((4 + 8 + fmt.len() as u32 + 8 + data.len() as u32).to_le_bytes());
+        file.extend(b"WAVE");
+        file.extend(b"fmt ");
+        file.extend((fmt.len() as u32).to_le_bytes());
+        file.extend(fmt);
+        file.extend(b"data");
+        file.extend((data.len() as u32).to_le_bytes());
+        file.extend(data);
+        file
+    }
+
+    /// 16 bit pcm, with one slice of samples per channel
+    fn pcm_wav(channels: &[&[i16]]) -> Vec<u8> {
+        let data = (0..channels[0].len())
+            .flat_map(|index| channels.iter().map(move |channel| channel[index]))
+            .flat_map(i16::to_le_bytes)
+            .collect::<Vec<_>>();
+
+        wav(
+            1,
+            channels.len() as u16,
+            2 * channels.len() as u16,
+            16,
+            &[],
+            &data,
+        )
+    }
+
+    /// 2.5 seconds of a sawtooth at 8khz, so every sample in a half second stretch is different
+    fn ramp() -> Vec<i16> {
+        (0..20_000).map(|v| (v % 4000 - 2000) as i16).collect()
+    }
+
+    fn decode(file: Vec<u8>) -> ConvertingAudioIterator<f32> {
+        ConvertingAudioIterator::new(AudioSource::from_memory_buffer(file)).unwrap()
+    }
+
+    const IMA_BLOCK_ALIGN: u16 = 1024;
+    /// Frames in each mono ima adpcm block, the header holds the first and every other byte holds two
+    const IMA_FRAMES_PER_BLOCK: usize = (IMA_BLOCK_ALIGN as usize - 4) * 2 + 1;
+
+    /// Mono ima adpcm, where every block is decoded on its own and is big enough to be its own packet
+    fn ima_wav(blocks: usize, corrupt: usize) -> Vec<u8> {
+        let frames_per_block = IMA_FRAMES_PER_BLOCK as u16;
+        let data = (0..blocks)
+            .flat_map(|block| {
+                // predictor, step index and a reserved byte, with step indices above 88 being invalid
+                let step_index = if block == corrupt { 89 } else { 20 };
+                let header = [0, 0, step_index, 0];
+                let nibbles =
+                    (0..IMA_BLOCK_ALIGN - 4).map(move |byte| (byte as usize * 7 + block) as u8);
+                header.into_iter().chain(nibbles)
+            })
+            .collect::<Vec<_>>();
+        // the size of the extension, which only holds the frames per block
+        let [low, high] = frames_per_block.to_le_bytes();
+        let extra = [2, 0, low, high];
+
+        wav(0x11, 1, IMA_BLOCK_ALIGN, 4, &extra, &data)
+    }
+
+    #[test]
+    fn corrupt_packets_are_skipped() {
+        let clean = decode(ima_wav(4, usize::MAX)).collect::<Vec<_>>();
+        assert_eq!(clean.len(), 4 * IMA_FRAMES_PER_BLOCK);
+
+        let mut decoded = decode(ima_wav(4, 1));
+        let samples = decoded.by_ref().collect::<Vec<_>>();
+
+        // decoding carries on with the blocks after the corrupt one
+        let mut expected = clean[..IMA_FRAMES_PER_BLOCK].to_vec();
+        expected.extend(&clean[2 * IMA_FRAMES_PER_BLOCK..]);
+        assert_eq!(samples, expected);
+
+        let health = decoded.health();
+        assert_eq!(health.packets_skipped, 1);
+        assert_eq!(health.samples_lost, IMA_FRAMES_PER_BLOCK as u64);
+        assert_eq!(health.resets, 0);
+        assert!(!health.ended_early);
+        assert!(!health.is_clean());
+        assert!(decoded.error().is_none());
+    }
+
+    #[test]
+    fn clean_decode_is_healthy() {
+        let mut decoded = decode(ima_wav(4, usize::MAX));
+        decoded.by_ref().for_each(drop);
+
+        let health = decoded.health();
+        assert_eq!(health.packets_skipped, 0);
+        assert_eq!(health.samples_lost, 0);
+        assert!(health.is_clean());
+    }
+
+    #[test]
+    fn reset_rebuilds_the_decoder_without_changing_samples() {
+        let ramp = ramp();
+        let clean = decode(pcm_wav(&[&ramp])).collect::<Vec<_>>();
+
+        let mut decoded = decode(pcm_wav(&[&ramp]));
+        let mut samples = decoded.by_ref().take(5000).collect::<Vec<_>>();
+        decoded.reset().unwrap();
+        samples.extend(decoded.by_ref());
+
+        assert_eq!(samples, clean);
+        assert_eq!(decoded.health().resets, 1);
+        assert!(decoded.health().is_clean());
+    }
+
+    fn decode_range(file: Vec<u8>, start: f64, end: Option<f64>) -> Result<Vec<f32>, Error> {
+        let time_range = TimeRange {
+            start: Duration::from_secs_f64(start),
+            end: end.map(Duration::from_secs_f64),
+        };
+
+        Ok(decode(file).with_time_range(time_range)?.collect())
+    }
+
+    #[test]
+    fn time_range_trims_to_the_sample() {
+        let ramp = ramp();
+        let clean = decode(pcm_wav(&[&ramp])).collect::<Vec<_>>();
+
+        let samples = decode_range(pcm_wav(&[&ramp]), 0.5, Some(1.5)).unwrap();
+        assert_eq!(samples, clean[4000..12000]);
+
+        let samples = decode_range(pcm_wav(&[&ramp]), 0.0, Some(0.25)).unwrap();
+        assert_eq!(samples, clean[..2000]);
+
+        let samples = decode_range(pcm_wav(&[&ramp]), 1.0, None).unwrap();
+        assert_eq!(samples, clean[8000..]);
+    }
+
+    #[test]
+    fn time_range_past_the_end_is_cut_short() {
+        let ramp = ramp();
+        let clean = decode(pcm_wav(&[&ramp])).collect::<Vec<_>>();
+
+        let samples = decode_range(pcm_wav(&[&ramp]), 2.0, Some(10.0)).unwrap();
+        assert_eq!(samples, clean[16000..]);
+
+        let samples = decode_range(pcm_wav(&[&ramp]), 5.0, Some(10.0)).unwrap();
+        assert!(samples.is_empty(), "{}", samples.len());
+    }
+
+    #[test]
+    fn empty_time_range_is_an_error() {
+        let ramp = vec![0; 8000];
+
+        let result = decode_range(pcm_wav(&[&ramp]), 0.5, Some(0.5));
+        assert!(
+            matches!(result, Err(Error::InvalidTimeRange { .. })),
+            "{result:?}"
+        );
+
+        let result = decode_range(pcm_wav(&[&ramp]), 0.5, Some(0.25));
+        assert!(
+            matches!(result, Err(Error::InvalidTimeRange { .. })),
+            "{result:?}"
+        );
+    }
+
+    fn decode_channels(file: Vec<u8>, channel_mode: ChannelMode) -> Vec<f32> {
+        decode(file).with_channel_mode(channel_mode).collect()
+    }
+
+    fn assert_samples_close(actual: &[f32], expected: impl IntoIterator<Item = f32>) {
+        let expected = expected.into_iter().collect::<Vec<_>>();
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 1e-6, "{a} != {e}");
+        }
+    }
+
+    #[test]
+    fn channel_modes_mix_stereo() {
+        let left = (0..4000).map(|v| (v * 8) as i16).collect::<Vec<_>>();
+        let right = (0..4000).map(|v| (v * -3 + 500) as i16).collect::<Vec<_>>();
+        let (l, r) = (
+            decode_channels(pcm_wav(&[&left]), ChannelMode::Average),
+            decode_channels(pcm_wav(&[&right]), ChannelMode::Average),
+        );
+        let pairs = || l.iter().zip(&r);
+
+        let average = decode_channels(pcm_wav(&[&left, &right]), ChannelMode::Average);
+        assert_samples_close(&average, pairs().map(|(l, r)| (l + r) / 2.0));
+
+        let mid = decode_channels(pcm_wav(&[&left, &right]), ChannelMode::Mid);
+        assert_samples_close(&mid, pairs().map(|(l, r)| (l + r) / 2.0));
+
+        let side = decode_channels(pcm_wav(&[&left, &right]), ChannelMode::Side);
+        assert_samples_close(&side, pairs().map(|(l, r)| (l - r) / 2.0));
+
+        let first = decode_channels(pcm_wav(&[&left, &right]), ChannelMode::Channel(0));
+        assert_eq!(first, l);
+
+        let second = decode_channels(pcm_wav(&[&left, &right]), ChannelMode::Channel(1));
+        assert_eq!(second, r);
+    }
+
+    #[test]
+    fn channel_modes_on_mono() {
+        let mono = (0..4000).map(|v| (v * 8) as i16).collect::<Vec<_>>();
+        let clean = decode_channels(pcm_wav(&[&mono]), ChannelMode::Channel(0));
+
+        assert_eq!(decode_channels(pcm_wav(&[&mono]), ChannelMode::Mid), clean);
+        assert_eq!(
+            decode_channels(pcm_wav(&[&mono]), ChannelMode::Side),
+            vec![0.0; clean.len()]
+        );
+    }
+
+    #[test]
+    fn missing_channel_stops_decoding() {
+        let silence = vec![0; 4000];
+
+        let mut decoded =
+            decode(pcm_wav(&[&silence, &silence])).with_channel_mode(ChannelMode::Channel(2));
+        assert_eq!(decoded.by_ref().count(), 0);
+
+        assert!(
+            matches!(
+                decoded.error(),
+                Some(Error::MissingChannel {
+                    channel: 2,
+                    channels: 2
+                })
+            ),
+            "{:?}",
+            decoded.error()
+        );
+        assert!(decoded.health().ended_early);
+    }
+
+    #[test]
+    fn max_energy_switches_once_when_channels_cross() {
+        const SAMPLE_RATE: u32 = 1000;
+        const PACKET_LEN: usize = 100;
+        let packets = 60 * SAMPLE_RATE as usize / PACKET_LEN;
+
+        let mut loudest = LoudestChannel::<f32>::new(SAMPLE_RATE);
+        let mut out = VecDeque::new();
+        let mut channels = Vec::new();
+        for packet in 0..packets {
+            // the first channel fades out as the second fades in, with every fifth packet swapping
+            // them around so following the loudest packet would keep switching
+            let fade = packet as f32 / packets as f32;
+            let (mut first, mut second) = (1.0 - fade, fade);
+            if packet % 5 == 4 {
+                (first, second) = (second, first);
+            }
+
+            let first = vec![first; PACKET_LEN];
+            let second = vec![second; PACKET_LEN];
+            ChannelMode::MaxEnergy
+                .mix_into(&[&first, &second], &mut loudest, &mut out)
+                .unwrap();
+            channels.push(loudest.channel.unwrap());
+        }
+
+        let switches = channels
+            .windows(2)
+            .filter(|pair| pair[0] != pair[1])
+            .count();
+        assert_eq!(switches, 1, "{channels:?}");
+        assert_eq!(channels.first(), Some(&0));
+        assert_eq!(channels.last(), Some(&1));
+
+        // the switch only happens once the second channel has been louder for a while
+        let switched_at = channels.iter().position(|channel| *channel == 1).unwrap();
+        assert!(switched_at > packets / 2, "{switched_at}");
+    }
+}
@@ -2,12 +2,30 @@ use std::{path::PathBuf, time::Duration};
 
 use tracing::instrument;
 
-use crate::LogSpectrogramIterator;
+use crate::features::{FeatureProcessor, FeatureSettings, Resolutions};
 
 const DEFAULT_SAMPLE_RATE: usize = 2 << 14;
 const DEFAULT_FFT_SIZE: usize = DEFAULT_SAMPLE_RATE;
-const DEFAULT_FTT_OVERLAP: usize = DEFAULT_FFT_SIZE / 4;
 const DEFAULT_MAX_FREQUENCY: usize = DEFAULT_SAMPLE_RATE / 2;
+const DEFAULT_KAISER_BETA: f32 = 8.6;
+const DEFAULT_DB_REFERENCE: f32 = 1.0;
+const DEFAULT_DB_FLOOR: f32 = -80.0;
+const DEFAULT_LOG1P_GAIN: f32 = 1.0;
+const DEFAULT_MFCC_COEFFICIENTS: usize = 13;
+const DEFAULT_MFCC_DELTA_WIDTH: usize = 2;
+const DEFAULT_CHROMA_MIN_FREQUENCY: usize = 32;
+const DEFAULT_CHROMA_TUNING: f32 = 440.0;
+const DEFAULT_CQT_MIN_FREQUENCY: usize = 55;
+const DEFAULT_CQT_BINS_PER_OCTAVE: usize = 12;
+const DEFAULT_CQT_OCTAVES: usize = 7;
+const DEFAULT_PEAK_TIME_RADIUS: usize = 3;
+const DEFAULT_PEAK_FREQUENCY_RADIUS: usize = 3;
+const DEFAULT_PEAK_BANDS: usize = 4;
+const DEFAULT_PEAKS_PER_BAND: usize = 2;
+const DEFAULT_LANDMARK_FAN_OUT: usize = 5;
+const DEFAULT_LANDMARK_MIN_TIME_DELTA: usize = 1;
+const DEFAULT_LANDMARK_MAX_TIME_DELTA: usize = 32;
+const DEFAULT_LANDMARK_MAX_FREQUENCY_DELTA: usize = 64;
 
 #[derive(Debug, clap::Parser, Clone)]
 pub struct Options {
@@ -20,11 +38,129 @@ pub struct Options {
     #[arg(long = "ignore")]
     pub ignore_paths: Vec<PathBuf>,
     #[command(flatten)]
+    pub tracks: TrackOptions,
+    #[command(flatten)]
+    pub time_range: TimeRangeOptions,
+    #[command(flatten)]
+    pub channels: ChannelOptions,
+    #[command(flatten)]
     pub resampler: ResampleSettings,
     #[command(flatten)]
     pub spectrogram: SpectrogramSettings,
     #[command(flatten)]
     pub log_settings: LogSpectrogramSettings,
+    #[command(flatten)]
+    pub features: FeatureOptions,
+    #[command(flatten)]
+    pub fingerprints: FingerprintOptions,
+}
+
+impl Options {
+    /// Checks options against each other, which clap can't do while parsing them one at a time
+    pub fn validate(&self) -> Result<(), clap::Error> {
+        self.spectrogram.validate()?;
+        self.log_settings.validate()?;
+        self.features.validate(
+            self.log_settings.height,
+            self.resampler.resample_rate,
+            &self.spectrogram.extra_window_sizes,
+        )?;
+        self.fingerprints.validate(
+            self.features.feature,
+            crate::file::BuildSettings::from(self.clone()).vector_length() as usize,
+        )
+    }
+}
+
+/// A usage error for options that parsed but don't make sense together
+fn invalid(message: String) -> clap::Error {
+    clap::Error::raw(clap::error::ErrorKind::ValueValidation, message)
+}
+
+#[derive(Debug, clap::Args, Clone)]
+pub struct TrackOptions {
+    /// Index of the track to decode from files with several, defaults to the file's default track
+    #[arg(long, conflicts_with = "track_language")]
+    pub track: Option<usize>,
+    /// Decode the first track in this language instead of the default track, e.g. `eng`
+    #[arg(long)]
+    pub track_language: Option<String>,
+}
+
+impl TrackOptions {
+    pub fn track_selection(&self) -> pleep_audio::TrackSelection {
+        match (self.track, &self.track_language) {
+            (Some(index), _) => pleep_audio::TrackSelection::Index(index),
+            (None, Some(language)) => pleep_audio::TrackSelection::Language(language.to_owned()),
+            (None, None) => pleep_audio::TrackSelection::Default,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args, Clone)]
+pub struct TimeRangeOptions {
+    /// Only use audio after this point, in seconds or `minutes:seconds`
+    #[arg(long, value_parser = parse_time)]
+    pub start: Option<Duration>,
+    /// Only use audio before this point, in seconds or `minutes:seconds`
+    #[arg(long, value_parser = parse_time)]
+    pub end: Option<Duration>,
+}
+
+impl TimeRangeOptions {
+    /// Fails with a usage error if the end isn't after the start
+    pub fn time_range(&self) -> Result<pleep_audio::TimeRange, clap::Error> {
+        let start = self.start.unwrap_or_default();
+        match self.end {
+            Some(end) if end <= start => Err(clap::Error::raw(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("--end ({end:?}) must be after --start ({start:?})"),
+            )),
+            end => Ok(pleep_audio::TimeRange { start, end }),
+        }
+    }
+}
+
+#[derive(Debug, clap::Args, Clone)]
+pub struct ChannelOptions {
+    /// How the channels of multi-channel audio are turned into one
+    #[arg(long, value_enum, default_value_t = ChannelMode::Average)]
+    pub channel_mode: ChannelMode,
+    /// Index of the channel used by the channel mode, starting from zero
+    #[arg(long, default_value_t = 0)]
+    pub channel: usize,
+}
+
+#[derive(Debug, clap::ValueEnum, Clone, Copy)]
+pub enum ChannelMode {
+    /// Average every channel
+    Average,
+    /// Only use the channel given by `--channel`
+    Channel,
+    /// Average the left and right channels
+    Mid,
+    /// Half the difference between the left and right channels
+    Side,
+    /// Use the loudest channel, only switching once another one has been clearly louder for a while
+    MaxEnergy,
+}
+
+impl ChannelMode {
+    pub fn channel_mode(self, channel: usize) -> pleep_audio::ChannelMode {
+        match self {
+            ChannelMode::Average => pleep_audio::ChannelMode::Average,
+            ChannelMode::Channel => pleep_audio::ChannelMode::Channel(channel),
+            ChannelMode::Mid => pleep_audio::ChannelMode::Mid,
+            ChannelMode::Side => pleep_audio::ChannelMode::Side,
+            ChannelMode::MaxEnergy => pleep_audio::ChannelMode::MaxEnergy,
+        }
+    }
+}
+
+impl ChannelOptions {
+    pub fn channel_mode(&self) -> pleep_audio::ChannelMode {
+        self.channel_mode.channel_mode(self.channel)
+    }
 }
 
 #[derive(Debug, clap::Args, Clone)]
@@ -32,29 +168,412 @@ pub struct SpectrogramSettings {
     /// Amount of samples per fft
     #[arg(long, default_value_t = DEFAULT_FFT_SIZE)]
     pub fft_size: usize,
-    /// Amount of samples each fft will overlap with the previous fft
-    #[arg(long, default_value_t = DEFAULT_FTT_OVERLAP)]
-    pub fft_overlap: usize,
+    /// Amount of samples in each window, zero padded up to the fft size. Defaults to the fft size
+    #[arg(long)]
+    pub window_size: Option<usize>,
+    /// Amount of samples each window will overlap with the previous window, must be smaller than the
+    /// window size. Defaults to a quarter of the window size
+    #[arg(long)]
+    pub fft_overlap: Option<usize>,
+    /// Pad the start of the audio by half a window, so that the first window is centred on the first sample
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub center: bool,
+    /// Additional window sizes computed on the same hop grid as the main window, must not be longer than the fft size
+    #[arg(long, value_delimiter = ',')]
+    pub extra_window_sizes: Vec<usize>,
+    /// How the spectrograms of each window size are combined when extra window sizes are given
+    #[arg(long, value_enum, default_value_t = ResolutionCombine::Stack)]
+    pub resolution_combine: ResolutionCombine,
+    /// Window function applied to each fft
+    #[arg(long, value_enum, default_value_t = WindowFunction::Hann)]
+    pub window: WindowFunction,
+    /// Beta parameter used by the kaiser window
+    #[arg(long, default_value_t = DEFAULT_KAISER_BETA)]
+    pub kaiser_beta: f32,
+}
+
+#[derive(Debug, clap::ValueEnum, Clone, Copy)]
+pub enum WindowFunction {
+    Hann,
+    Hamming,
+    Blackman,
+    BlackmanHarris,
+    Kaiser,
+    Rectangular,
+    FlatTop,
+}
+
+#[derive(Debug, clap::ValueEnum, Clone, Copy)]
+pub enum ResolutionCombine {
+    /// Put the feature vectors of each window size one after the other
+    Stack,
+    /// Average the spectrograms of each window size before making the feature vector
+    Mean,
+}
+
+impl SpectrogramSettings {
+    pub fn window_len(&self) -> usize {
+        self.window_size.unwrap_or(self.fft_size)
+    }
+
+    pub fn fft_overlap(&self) -> usize {
+        self.fft_overlap.unwrap_or(self.window_len() / 4)
+    }
+
+    pub fn validate(&self) -> Result<(), clap::Error> {
+        let window_len = self.window_len();
+        if window_len > self.fft_size {
+            return Err(invalid(format!(
+                "--window-size ({window_len}) must not be larger than --fft-size ({})",
+                self.fft_size
+            )));
+        }
+        if self.fft_overlap() >= window_len {
+            return Err(invalid(format!(
+                "--fft-overlap ({}) must be smaller than the window size ({window_len})",
+                self.fft_overlap()
+            )));
+        }
+        for window_size in &self.extra_window_sizes {
+            if *window_size == 0 || *window_size > self.fft_size {
+                return Err(invalid(format!(
+                    "--extra-window-sizes entries must be between 1 and --fft-size ({}), got {window_size}",
+                    self.fft_size
+                )));
+            }
+        }
+        if !self.kaiser_beta.is_finite() || self.kaiser_beta < 0.0 {
+            return Err(invalid(format!(
+                "--kaiser-beta must be finite and not negative, got {}",
+                self.kaiser_beta
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn resolution_combine(&self) -> pleep::multires::Combine {
+        match self.resolution_combine {
+            ResolutionCombine::Stack => pleep::multires::Combine::Stack,
+            ResolutionCombine::Mean => pleep::multires::Combine::Mean,
+        }
+    }
+
+    pub fn window_function(&self) -> pleep::window::WindowFunction {
+        match self.window {
+            WindowFunction::Hann => pleep::window::WindowFunction::Hann,
+            WindowFunction::Hamming => pleep::window::WindowFunction::Hamming,
+            WindowFunction::Blackman => pleep::window::WindowFunction::Blackman,
+            WindowFunction::BlackmanHarris => pleep::window::WindowFunction::BlackmanHarris,
+            WindowFunction::Kaiser => pleep::window::WindowFunction::Kaiser {
+                beta: self.kaiser_beta,
+            },
+            WindowFunction::Rectangular => pleep::window::WindowFunction::Rectangular,
+            WindowFunction::FlatTop => pleep::window::WindowFunction::FlatTop,
+        }
+    }
 }
 
 #[derive(Debug, clap::Args, Clone)]
 pub struct LogSpectrogramSettings {
-    /// Height of make the spectrogram when converting to log
+    /// Height of make the spectrogram when converting to log, or the number of mel bands
     #[arg(long = "spectrogram-height", default_value_t = 200)]
     pub height: usize,
-    /// Maximum frequency of the log spectrogram
+    /// Minimum frequency of the log spectrogram
+    #[arg(long = "spectrogram-min-frequency", default_value_t = 0, value_parser = parse_frequency)]
+    pub min_frequency: usize,
+    /// Maximum frequency of the log or mel spectrogram
     #[arg(long = "spectrogram-max-frequency", default_value_t = DEFAULT_MAX_FREQUENCY, value_parser = parse_frequency)]
     pub max_frequency: usize,
     /// The base the use when transforming to a log graph
     #[arg(long, default_value_t = 9.5)]
     pub log_base: f32,
+    /// How the rows of the log spectrogram are spaced in frequency
+    #[arg(long, value_enum, default_value_t = crate::FrequencyScale::Log)]
+    pub frequency_scale: crate::FrequencyScale,
+    /// How magnitudes are compressed before the log spectrogram is made
+    #[arg(long, value_enum, default_value_t = MagnitudeScale::Linear)]
+    pub magnitude_scale: MagnitudeScale,
+    /// Magnitude that is 0db when using the decibels magnitude scale
+    #[arg(long, default_value_t = DEFAULT_DB_REFERENCE)]
+    pub db_reference: f32,
+    /// Quietest value in decibels, anything quieter is raised to this
+    #[arg(long, default_value_t = DEFAULT_DB_FLOOR, allow_negative_numbers = true)]
+    pub db_floor: f32,
+    /// Multiplier applied to magnitudes before `ln(1 + x)` when using the log1p magnitude scale
+    #[arg(long, default_value_t = DEFAULT_LOG1P_GAIN)]
+    pub log1p_gain: f32,
+    /// How the linear values covered by each row of the log spectrogram are combined
+    #[arg(long, value_enum, default_value_t = crate::Aggregation::Max)]
+    pub log_aggregation: crate::Aggregation,
+}
+
+#[derive(Debug, clap::ValueEnum, Clone, Copy)]
+pub enum MagnitudeScale {
+    Linear,
+    Power,
+    Decibels,
+    Log1p,
+}
+
+impl LogSpectrogramSettings {
+    pub fn validate(&self) -> Result<(), clap::Error> {
+        if self.min_frequency >= self.max_frequency {
+            return Err(invalid(format!(
+                "--spectrogram-min-frequency ({}) must be below --spectrogram-max-frequency ({})",
+                self.min_frequency, self.max_frequency
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn magnitude_scale(&self) -> pleep::magnitude::MagnitudeScale {
+        match self.magnitude_scale {
+            MagnitudeScale::Linear => pleep::magnitude::MagnitudeScale::Linear,
+            MagnitudeScale::Power => pleep::magnitude::MagnitudeScale::Power,
+            MagnitudeScale::Decibels => pleep::magnitude::MagnitudeScale::Decibels {
+                reference: self.db_reference,
+                floor: self.db_floor,
+            },
+            MagnitudeScale::Log1p => pleep::magnitude::MagnitudeScale::Log1p {
+                gain: self.log1p_gain,
+            },
+        }
+    }
+}
+
+#[derive(Debug, clap::Args, Clone)]
+pub struct FeatureOptions {
+    /// The kind of vector to store for each column of a segment
+    #[arg(long, value_enum, default_value_t = Feature::LogSpectrogram)]
+    pub feature: Feature,
+    /// Minimum frequency of the mel filterbank
+    #[arg(long, default_value_t = 0, value_parser = parse_frequency)]
+    pub mel_min_frequency: usize,
+    /// Formula used to convert between hz and mels
+    #[arg(long, value_enum, default_value_t = MelScale::Htk)]
+    pub mel_scale: MelScale,
+    /// Number of cepstral coefficients to keep for each mfcc vector
+    #[arg(long, default_value_t = DEFAULT_MFCC_COEFFICIENTS)]
+    pub mfcc_coefficients: usize,
+    /// Derivatives to append to each mfcc vector
+    #[arg(long, value_enum, default_value_t = MfccDeltas::None)]
+    pub mfcc_deltas: MfccDeltas,
+    /// Number of vectors either side used when computing mfcc deltas
+    #[arg(long, default_value_t = DEFAULT_MFCC_DELTA_WIDTH)]
+    pub mfcc_delta_width: usize,
+    /// Minimum frequency folded into chroma vectors
+    #[arg(long, default_value_t = DEFAULT_CHROMA_MIN_FREQUENCY, value_parser = parse_frequency)]
+    pub chroma_min_frequency: usize,
+    /// Frequency of A4 used when assigning frequencies to pitch classes
+    #[arg(long, default_value_t = DEFAULT_CHROMA_TUNING)]
+    pub chroma_tuning: f32,
+    /// Centre frequency of the lowest constant-q bin
+    #[arg(long, default_value_t = DEFAULT_CQT_MIN_FREQUENCY, value_parser = parse_frequency)]
+    pub cqt_min_frequency: usize,
+    /// Number of constant-q bins in each octave
+    #[arg(long, default_value_t = DEFAULT_CQT_BINS_PER_OCTAVE)]
+    pub cqt_bins_per_octave: usize,
+    /// Number of octaves covered by the constant-q transform
+    #[arg(long, default_value_t = DEFAULT_CQT_OCTAVES)]
+    pub cqt_octaves: usize,
+}
+
+#[derive(Debug, clap::ValueEnum, Clone, Copy)]
+pub enum Feature {
+    LogSpectrogram,
+    MelSpectrogram,
+    Mfcc,
+    Chroma,
+    ConstantQ,
+}
+
+#[derive(Debug, clap::ValueEnum, Clone, Copy)]
+pub enum MfccDeltas {
+    None,
+    Delta,
+    DeltaDelta,
+}
+
+#[derive(Debug, clap::ValueEnum, Clone, Copy)]
+pub enum MelScale {
+    Htk,
+    Slaney,
+}
+
+impl FeatureOptions {
+    /// `height` is the number of mel bands that mfccs are taken from, and `extra_window_sizes` are the
+    /// multi-resolution windows, which constant-q has no use for
+    pub fn validate(
+        &self,
+        height: usize,
+        sample_rate: usize,
+        extra_window_sizes: &[usize],
+    ) -> Result<(), clap::Error> {
+        match self.feature {
+            Feature::Mfcc if self.mfcc_coefficients > height => Err(invalid(format!(
+                "--mfcc-coefficients ({}) must not be more than --spectrogram-height ({height})",
+                self.mfcc_coefficients
+            ))),
+            Feature::ConstantQ if !extra_window_sizes.is_empty() => Err(invalid(
+                "--extra-window-sizes can't be used with --feature constant-q, which picks its own \
+                 window lengths"
+                    .to_string(),
+            )),
+            Feature::ConstantQ if self.cqt_bins_per_octave == 0 => Err(invalid(
+                "--cqt-bins-per-octave must be at least 1".to_string(),
+            )),
+            Feature::ConstantQ if self.cqt_min_frequency == 0 => {
+                Err(invalid("--cqt-min-frequency must be above 0hz".to_string()))
+            }
+            Feature::ConstantQ => {
+                let bins = self.cqt_bins_per_octave * self.cqt_octaves;
+                let max_frequency = self.cqt_min_frequency as f64
+                    * 2f64.powf(bins.saturating_sub(1) as f64 / self.cqt_bins_per_octave as f64);
+                if max_frequency >= sample_rate as f64 / 2.0 {
+                    return Err(invalid(format!(
+                        "the highest constant-q bin ({max_frequency:.0}hz) must be below half of --resample-rate \
+                         ({sample_rate}hz), use fewer --cqt-octaves or a lower --cqt-min-frequency"
+                    )));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn feature_kind(&self) -> crate::features::FeatureKind {
+        use crate::features::FeatureKind;
+
+        match self.feature {
+            Feature::LogSpectrogram => FeatureKind::LogSpectrogram,
+            Feature::MelSpectrogram => FeatureKind::MelSpectrogram {
+                min_frequency: self.mel_min_frequency as u32,
+                scale: self.mel_scale(),
+            },
+            Feature::Mfcc => FeatureKind::Mfcc {
+                min_frequency: self.mel_min_frequency as u32,
+                scale: self.mel_scale(),
+                n_coefficients: self.mfcc_coefficients as u32,
+                delta_order: match self.mfcc_deltas {
+                    MfccDeltas::None => 0,
+                    MfccDeltas::Delta => 1,
+                    MfccDeltas::DeltaDelta => 2,
+                },
+                delta_width: self.mfcc_delta_width as u32,
+            },
+            Feature::Chroma => FeatureKind::Chroma {
+                min_frequency: self.chroma_min_frequency as u32,
+                tuning_frequency: self.chroma_tuning,
+            },
+            Feature::ConstantQ => FeatureKind::ConstantQ {
+                min_frequency: self.cqt_min_frequency as u32,
+                bins_per_octave: self.cqt_bins_per_octave as u32,
+                n_octaves: self.cqt_octaves as u32,
+            },
+        }
+    }
+
+    fn mel_scale(&self) -> pleep::mel::MelScale {
+        match self.mel_scale {
+            MelScale::Htk => pleep::mel::MelScale::Htk,
+            MelScale::Slaney => pleep::mel::MelScale::Slaney,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args, Clone)]
+pub struct FingerprintOptions {
+    /// Also store an index of landmark hashes, allowing `pleep-search --mode hashes`
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub fingerprints: bool,
+    /// Number of vectors either side that a peak must be the loudest over
+    #[arg(long, default_value_t = DEFAULT_PEAK_TIME_RADIUS)]
+    pub peak_time_radius: usize,
+    /// Number of values either side that a peak must be the loudest over
+    #[arg(long, default_value_t = DEFAULT_PEAK_FREQUENCY_RADIUS)]
+    pub peak_frequency_radius: usize,
+    /// Peaks at or below this value are ignored
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub peak_threshold: f64,
+    /// Number of bands each vector is split into when limiting the number of peaks
+    #[arg(long, default_value_t = DEFAULT_PEAK_BANDS)]
+    pub peak_bands: usize,
+    /// Maximum number of peaks kept in each band of a vector
+    #[arg(long, default_value_t = DEFAULT_PEAKS_PER_BAND)]
+    pub peaks_per_band: usize,
+    /// Maximum number of later peaks each peak is paired with
+    #[arg(long, default_value_t = DEFAULT_LANDMARK_FAN_OUT)]
+    pub landmark_fan_out: usize,
+    /// Smallest number of vectors between paired peaks
+    #[arg(long, default_value_t = DEFAULT_LANDMARK_MIN_TIME_DELTA)]
+    pub landmark_min_time_delta: usize,
+    /// Largest number of vectors between paired peaks
+    #[arg(long, default_value_t = DEFAULT_LANDMARK_MAX_TIME_DELTA)]
+    pub landmark_max_time_delta: usize,
+    /// Largest number of values between paired peaks
+    #[arg(long, default_value_t = DEFAULT_LANDMARK_MAX_FREQUENCY_DELTA)]
+    pub landmark_max_frequency_delta: usize,
+}
+
+impl FingerprintOptions {
+    /// `vector_len` is the number of values in each vector that peaks are found in
+    pub fn validate(&self, feature: Feature, vector_len: usize) -> Result<(), clap::Error> {
+        if !self.fingerprints {
+            return Ok(());
+        }
+
+        if !matches!(feature, Feature::LogSpectrogram) {
+            return Err(invalid(
+                "--fingerprints can only be built from --feature log-spectrogram".to_string(),
+            ));
+        }
+        if vector_len > pleep::landmarks::MAX_BIN + 1 {
+            return Err(invalid(format!(
+                "--fingerprints can only hash vectors of up to {} values, these have {vector_len}",
+                pleep::landmarks::MAX_BIN + 1
+            )));
+        }
+        if self.landmark_max_time_delta > pleep::landmarks::MAX_TIME_DELTA {
+            return Err(invalid(format!(
+                "--landmark-max-time-delta ({}) must be at most {}",
+                self.landmark_max_time_delta,
+                pleep::landmarks::MAX_TIME_DELTA
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn peak_settings(&self) -> pleep::peaks::Settings {
+        pleep::peaks::Settings {
+            time_radius: self.peak_time_radius,
+            frequency_radius: self.peak_frequency_radius,
+            threshold: self.peak_threshold,
+            n_bands: self.peak_bands,
+            max_peaks_per_band: self.peaks_per_band,
+        }
+    }
+
+    pub fn landmark_settings(&self) -> pleep::landmarks::Settings {
+        pleep::landmarks::Settings {
+            fan_out: self.landmark_fan_out,
+            min_time_delta: self.landmark_min_time_delta,
+            max_time_delta: self.landmark_max_time_delta,
+            max_frequency_delta: self.landmark_max_frequency_delta,
+        }
+    }
 }
 
 impl From<SpectrogramSettings> for pleep::spectrogram::Settings {
     fn from(val: SpectrogramSettings) -> Self {
         pleep::spectrogram::Settings {
             fft_len: val.fft_size,
-            fft_overlap: val.fft_overlap,
+            window_len: val.window_len(),
+            fft_overlap: val.fft_overlap(),
+            window_function: val.window_function(),
+            center: val.center,
         }
     }
 }
@@ -82,44 +601,66 @@ impl From<ResampleSettings> for pleep_audio::ResampleSettings {
     }
 }
 
-#[instrument(level = "trace")]
-pub fn file_to_log_spectrogram(
+/// How audio files are turned into the single channel of samples that gets resampled
+#[derive(Debug, Clone, Default)]
+pub struct DecodeSettings {
+    pub track: pleep_audio::TrackSelection,
+    pub time_range: pleep_audio::TimeRange,
+    pub channel_mode: pleep_audio::ChannelMode,
+}
+
+#[instrument(level = "trace", skip(generator))]
+pub fn file_to_features(
     path: &PathBuf,
+    decode_settings: &DecodeSettings,
     spectrogram_settings: &pleep::spectrogram::Settings,
+    resolutions: &Resolutions,
     resample_settings: &pleep_audio::ResampleSettings,
-    log_spectrogram_settings: &LogSpectrogramSettings,
-) -> (
-    Duration,
-    LogSpectrogramIterator<f32, std::vec::IntoIter<f32>>,
-) {
-    let audio = pleep_audio::ConvertingAudioIterator::new(
-        pleep_audio::AudioSource::from_file_path(path).expect("failed to get audio source"),
-    )
-    .expect("failed to load file");
+    feature_settings: &FeatureSettings,
+    generator: &pleep::spectrogram::Generator<f32>,
+) -> Result<(Duration, Vec<Vec<f32>>, pleep_audio::DecodeHealth), LoadError> {
+    let mut audio = pleep_audio::ConvertingAudioIterator::new_with_track(
+        pleep_audio::AudioSource::from_file_path(path)?,
+        &decode_settings.track,
+    )?
+    .with_time_range(decode_settings.time_range)?
+    .with_channel_mode(decode_settings.channel_mode);
 
-    let resampled = pleep_audio::ResamplingChunksIterator::new_from_audio_iterator(
-        audio,
+    let sample_rate = audio.sample_rate() as usize;
+    let resampled = pleep_audio::ResamplingChunksIterator::new(
+        audio.by_ref(),
+        sample_rate,
         resample_settings.to_owned(),
     )
-    .expect("failed to create resampler")
+    .map_err(pleep_audio::Error::from)?
     .flatten()
     .collect::<Vec<f32>>();
 
-    (
+    let health = audio.health().to_owned();
+    // only the rest of the track is missing, but a partial track would be indexed as if it were whole
+    if let Some(error) = audio.into_error() {
+        return Err(error.into());
+    }
+
+    let mut processor = FeatureProcessor::new(
+        spectrogram_settings,
+        resolutions,
+        feature_settings,
+        generator,
+    );
+    let mut features = Vec::new();
+    processor.process_into_vec(&resampled, &mut features);
+
+    Ok((
         Duration::from_secs_f64(
             resampled.len() as f64 / resample_settings.target_sample_rate as f64,
         ),
-        crate::generate_log_spectrogram(
-            resampled,
-            spectrogram_settings,
-            &crate::LogSpectrogramSettings {
-                height: log_spectrogram_settings.height,
-                frequency_cutoff: log_spectrogram_settings.max_frequency,
-                input_sample_rate: resample_settings.target_sample_rate,
-                base: log_spectrogram_settings.log_base,
-            },
-        ),
-    )
+        features
+            .chunks_exact(processor.height())
+            .map(<[f32]>::to_vec)
+            .collect(),
+        health,
+    ))
 }
 
 pub fn parse_frequency(input: &str) -> Result<usize, ParseFrequencyError> {
@@ -128,7 +669,7 @@ pub fn parse_frequency(input: &str) -> Result<usize, ParseFrequencyError> {
 
     let multiplier = if lower.ends_with("k") {
         1000
-    } else if lower.chars().map(|c| c.is_numeric()).all(|v| v) {
+    } else if lower.chars().all(|c| c.is_numeric()) {
         1
     } else {
         return Err(ParseFrequencyError::InvalidText(input.to_string()));
@@ -146,6 +687,52 @@ pub fn parse_frequency(input: &str) -> Result<usize, ParseFrequencyError> {
     Ok(freq * multiplier)
 }
 
+/// Parses a time given in seconds, e.g. `90` or `1.5s`, or with minutes and hours, e.g. `1:30`
+pub fn parse_time(input: &str) -> Result<Duration, ParseTimeError> {
+    let trimmed = input.trim();
+    let trimmed = trimmed.strip_suffix('s').unwrap_or(trimmed);
+
+    let mut seconds = 0.0;
+    for (index, part) in trimmed.split(':').enumerate() {
+        let value = part
+            .parse::<f64>()
+            .map_err(|error| ParseTimeError::ParseFloat {
+                text: input.to_string(),
+                original: error,
+            })?;
+
+        // only the leading part may count past a minute, `1:75` is more likely a typo than 2:15
+        if !value.is_finite() || value < 0.0 || (index > 0 && value >= 60.0) {
+            return Err(ParseTimeError::OutOfRange(input.to_string()));
+        }
+
+        seconds = seconds * 60.0 + value;
+    }
+
+    Duration::try_from_secs_f64(seconds).map_err(|_| ParseTimeError::OutOfRange(input.to_string()))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("failed to open file: {0}")]
+    Open(#[from] std::io::Error),
+    #[error("failed to decode file: {0}")]
+    Decode(#[from] pleep_audio::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseTimeError {
+    #[error("failed to parse string to a number: {text:?} {original:?}")]
+    ParseFloat {
+        text: String,
+        original: std::num::ParseFloatError,
+    },
+    #[error(
+        "time must not be negative or too large, and minutes and seconds after a colon must be below 60: {0}"
+    )]
+    OutOfRange(String),
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ParseFrequencyError {
     #[error("invalid text: {0}")]
@@ -156,3 +743,71 @@ pub enum ParseFrequencyError {
         original: std::num::ParseIntError,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_parse_as_seconds_minutes_and_hours() {
+        for (input, expected) in [
+            ("90", 90.0),
+            ("1.5s", 1.5),
+            (" 2s ", 2.0),
+            ("1:30", 90.0),
+            ("0:59.5", 59.5),
+            ("1:00:05", 3605.0),
+            ("90:00", 5400.0),
+        ] {
+            assert_eq!(
+                parse_time(input).unwrap(),
+                Duration::from_secs_f64(expected),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn times_reject_negative_and_overflowing_parts() {
+        for input in [
+            "-1", "1:-30", "1:75", "1:60", "1:00:60", "inf", "1:nan", "1::30", "1e30",
+        ] {
+            assert!(parse_time(input).is_err(), "{input:?}");
+        }
+    }
+
+    fn validate(args: &[&str]) -> Result<(), clap::Error> {
+        use clap::Parser;
+
+        Options::try_parse_from(["pleep-build", "out"].iter().chain(args))?.validate()
+    }
+
+    #[test]
+    fn fingerprints_need_log_spectrograms_that_fit_in_a_hash() {
+        assert!(validate(&["--fingerprints"]).is_ok());
+        assert!(validate(&["--fingerprints", "--feature", "mfcc"]).is_err());
+        assert!(validate(&["--fingerprints", "--feature", "chroma"]).is_err());
+        assert!(validate(&["--fingerprints", "--spectrogram-height", "1024"]).is_ok());
+        assert!(validate(&["--fingerprints", "--spectrogram-height", "1025"]).is_err());
+        // stacked resolutions make each vector longer
+        assert!(validate(&[
+            "--fingerprints",
+            "--spectrogram-height",
+            "600",
+            "--extra-window-sizes",
+            "1024"
+        ])
+        .is_err());
+        assert!(validate(&["--fingerprints", "--landmark-max-time-delta", "4096"]).is_err());
+
+        // without fingerprints, none of this matters
+        assert!(validate(&["--feature", "mfcc", "--spectrogram-height", "2000"]).is_ok());
+    }
+
+    #[test]
+    fn constant_q_rejects_extra_window_sizes() {
+        assert!(validate(&["--feature", "constant-q"]).is_ok());
+        assert!(validate(&["--feature", "constant-q", "--extra-window-sizes", "1024"]).is_err());
+        assert!(validate(&["--extra-window-sizes", "1024"]).is_ok());
+    }
+}
@@ -0,0 +1,450 @@
+use pleep::{
+    chroma::ChromaFilterbank,
+    cqt::ConstantQProcessor,
+    magnitude::MagnitudeScale,
+    mel::MelFilterbank,
+    mfcc::Mfcc,
+    multires::{Combine, MultiResolutionProcessor},
+    spectrogram::{Float, FrameProcessor, Generator, SpectrogramProcessor},
+};
+
+use crate::{get_cutoff_bin, LogResampler, LogSpectrogramSettings};
+
+/// The kind of vector stored for each column of a segment, as recorded in the file header
+#[derive(Debug, Clone, Copy)]
+pub enum FeatureKind {
+    LogSpectrogram,
+    MelSpectrogram {
+        min_frequency: u32,
+        scale: pleep::mel::MelScale,
+    },
+    Mfcc {
+        min_frequency: u32,
+        scale: pleep::mel::MelScale,
+        n_coefficients: u32,
+        delta_order: u32,
+        delta_width: u32,
+    },
+    Chroma {
+        min_frequency: u32,
+        tuning_frequency: f32,
+    },
+    ConstantQ {
+        min_frequency: u32,
+        bins_per_octave: u32,
+        n_octaves: u32,
+    },
+}
+
+/// The kind of vector stored for each column of a segment, and the settings needed to produce it
+#[derive(Debug, Clone)]
+pub enum FeatureSettings {
+    LogSpectrogram(LogSpectrogramSettings),
+    MelSpectrogram {
+        mel: pleep::mel::Settings,
+        input_sample_rate: usize,
+    },
+    Mfcc {
+        mfcc: pleep::mfcc::Settings,
+        input_sample_rate: usize,
+    },
+    Chroma {
+        chroma: pleep::chroma::Settings,
+        input_sample_rate: usize,
+    },
+    ConstantQ(pleep::cqt::Settings),
+}
+
+/// Window lengths whose spectrograms are computed alongside the main one, on the same hop grid
+#[derive(Debug, Clone, Default)]
+pub struct Resolutions {
+    /// Empty when only the main window is used
+    pub extra_window_lens: Vec<usize>,
+    pub combine: Combine,
+}
+
+impl Resolutions {
+    /// Number of columns each frame's vector is made of, each with the feature's usual height
+    pub fn stacked(&self, settings: &FeatureSettings) -> usize {
+        match (settings, self.combine) {
+            (FeatureSettings::ConstantQ(_), _) | (_, Combine::Mean) => 1,
+            (_, Combine::Stack) => 1 + self.extra_window_lens.len(),
+        }
+    }
+}
+
+/// Produces the linear columns that each [`ColumnTransform`] reads from
+enum FrontEnd<S: Float> {
+    Spectrogram(SpectrogramProcessor<S>),
+    MultiResolution(MultiResolutionProcessor<S>),
+    ConstantQ(ConstantQProcessor<S>),
+}
+
+impl<S: Float> FrontEnd<S> {
+    fn new(
+        spectrogram_settings: &pleep::spectrogram::Settings,
+        resolutions: &Resolutions,
+        settings: &FeatureSettings,
+        generator: &Generator<S>,
+    ) -> Self {
+        match settings {
+            FeatureSettings::ConstantQ(settings) => {
+                FrontEnd::ConstantQ(ConstantQProcessor::new(settings.to_owned(), generator))
+            }
+            _ if resolutions.extra_window_lens.is_empty() => FrontEnd::Spectrogram(
+                SpectrogramProcessor::new(spectrogram_settings.to_owned(), generator),
+            ),
+            _ => FrontEnd::MultiResolution(MultiResolutionProcessor::new(
+                pleep::multires::Settings {
+                    fft_len: spectrogram_settings.fft_len,
+                    window_lens: std::iter::once(spectrogram_settings.window_len)
+                        .chain(resolutions.extra_window_lens.iter().copied())
+                        .collect(),
+                    hop_len: spectrogram_settings.hop_len(),
+                    window_function: spectrogram_settings.window_function,
+                    center: spectrogram_settings.center,
+                    combine: resolutions.combine,
+                },
+                generator,
+            )),
+        }
+    }
+}
+
+impl<S: Float> FrameProcessor<S> for FrontEnd<S> {
+    fn frame_len(&self) -> usize {
+        match self {
+            FrontEnd::Spectrogram(processor) => processor.frame_len(),
+            FrontEnd::MultiResolution(processor) => processor.frame_len(),
+            FrontEnd::ConstantQ(processor) => processor.frame_len(),
+        }
+    }
+
+    fn hop_len(&self) -> usize {
+        match self {
+            FrontEnd::Spectrogram(processor) => processor.hop_len(),
+            FrontEnd::MultiResolution(processor) => processor.hop_len(),
+            FrontEnd::ConstantQ(processor) => processor.hop_len(),
+        }
+    }
+
+    fn bins(&self) -> usize {
+        match self {
+            FrontEnd::Spectrogram(processor) => processor.bins(),
+            FrontEnd::MultiResolution(processor) => processor.bins(),
+            FrontEnd::ConstantQ(processor) => processor.bins(),
+        }
+    }
+
+    fn start_padding(&self) -> usize {
+        match self {
+            FrontEnd::Spectrogram(processor) => processor.start_padding(),
+            FrontEnd::MultiResolution(processor) => processor.start_padding(),
+            FrontEnd::ConstantQ(processor) => processor.start_padding(),
+        }
+    }
+
+    fn process_frame(&mut self, frame: &[S], out: &mut [S]) {
+        match self {
+            FrontEnd::Spectrogram(processor) => processor.process_frame(frame, out),
+            FrontEnd::MultiResolution(processor) => processor.process_frame(frame, out),
+            FrontEnd::ConstantQ(processor) => processor.process_frame(frame, out),
+        }
+    }
+}
+
+enum ColumnTransform<S: Float> {
+    Log {
+        cutoff_bin: usize,
+        magnitude_scale: MagnitudeScale,
+        resampler: LogResampler,
+        /// The linear column after the magnitude scale has been applied
+        scaled: Vec<S>,
+    },
+    Mel(MelFilterbank<S>),
+    Mfcc(Mfcc<S>),
+    Chroma(ChromaFilterbank),
+    Identity {
+        height: usize,
+    },
+}
+
+impl<S: Float> ColumnTransform<S> {
+    fn new(
+        spectrogram_settings: &pleep::spectrogram::Settings,
+        settings: &FeatureSettings,
+        bins: usize,
+    ) -> Self {
+        match settings {
+            FeatureSettings::LogSpectrogram(settings) => {
+                let cutoff_bin = get_cutoff_bin(spectrogram_settings, settings);
+
+                ColumnTransform::Log {
+                    cutoff_bin,
+                    magnitude_scale: settings.magnitude_scale,
+                    resampler: LogResampler::from_settings(spectrogram_settings, settings),
+                    scaled: vec![S::zero(); cutoff_bin],
+                }
+            }
+            FeatureSettings::MelSpectrogram {
+                mel,
+                input_sample_rate,
+            } => ColumnTransform::Mel(MelFilterbank::new(
+                mel,
+                *input_sample_rate,
+                spectrogram_settings.fft_len,
+            )),
+            FeatureSettings::Mfcc {
+                mfcc,
+                input_sample_rate,
+            } => ColumnTransform::Mfcc(Mfcc::new(
+                mfcc,
+                *input_sample_rate,
+                spectrogram_settings.fft_len,
+            )),
+            FeatureSettings::Chroma {
+                chroma,
+                input_sample_rate,
+            } => ColumnTransform::Chroma(ChromaFilterbank::new(
+                chroma,
+                *input_sample_rate,
+                spectrogram_settings.fft_len,
+            )),
+            FeatureSettings::ConstantQ(_) => ColumnTransform::Identity { height: bins },
+        }
+    }
+
+    fn height(&self) -> usize {
+        match self {
+            ColumnTransform::Log { resampler, .. } => resampler.height(),
+            ColumnTransform::Mel(filterbank) => filterbank.n_mels(),
+            ColumnTransform::Mfcc(mfcc) => mfcc.height(),
+            ColumnTransform::Chroma(_) => pleep::chroma::N_CHROMA,
+            ColumnTransform::Identity { height } => *height,
+        }
+    }
+
+    /// Length of the linear column this transform reads from
+    fn input_len(&self, bins: usize) -> usize {
+        match self {
+            // anything past the last bin is left as zero, same as resizing the column
+            ColumnTransform::Log { cutoff_bin, .. } => bins.max(*cutoff_bin),
+            ColumnTransform::Mel(_)
+            | ColumnTransform::Mfcc(_)
+            | ColumnTransform::Chroma(_)
+            | ColumnTransform::Identity { .. } => bins,
+        }
+    }
+
+    /// Writes the vector for a single column, `out` is [`Self::height`] long
+    fn apply(&mut self, column: &[S], out: &mut [S]) {
+        match self {
+            ColumnTransform::Log {
+                cutoff_bin,
+                magnitude_scale,
+                resampler,
+                scaled,
+            } => {
+                scaled.copy_from_slice(&column[..*cutoff_bin]);
+                magnitude_scale.apply_in_place(scaled);
+                resampler.apply_into(scaled, out)
+            }
+            ColumnTransform::Mel(filterbank) => filterbank.apply_into(column, out),
+            ColumnTransform::Mfcc(mfcc) => {
+                let n_coefficients = mfcc.n_coefficients();
+                mfcc.apply_into(column, &mut out[..n_coefficients])
+            }
+            ColumnTransform::Chroma(filterbank) => filterbank.apply_into(column, out),
+            ColumnTransform::Identity { .. } => out.copy_from_slice(column),
+        }
+    }
+
+    /// Fills in anything that depends on neighbouring vectors, once every column has been applied.
+    ///
+    /// Each vector is `stacked` columns one after the other.
+    fn finish(&self, vectors: &mut [S], stacked: usize) {
+        if let ColumnTransform::Mfcc(mfcc) = self {
+            for index in 0..stacked {
+                mfcc.fill_deltas_at(vectors, stacked * mfcc.height(), index * mfcc.height());
+            }
+        }
+    }
+}
+
+/// Turns samples into flat `[frames × height]` buffers of feature vectors, reusing scratch space
+pub struct FeatureProcessor<S: Float> {
+    front_end: FrontEnd<S>,
+    /// Number of the front end's columns in each of its frames, each transformed separately
+    stacked: usize,
+    spectrum: Vec<S>,
+    column: Vec<S>,
+    transform: ColumnTransform<S>,
+}
+
+impl<S: Float> FeatureProcessor<S> {
+    pub fn new(
+        spectrogram_settings: &pleep::spectrogram::Settings,
+        resolutions: &Resolutions,
+        settings: &FeatureSettings,
+        generator: &Generator<S>,
+    ) -> Self {
+        let front_end = FrontEnd::new(spectrogram_settings, resolutions, settings, generator);
+        let stacked = resolutions.stacked(settings);
+        let bins = front_end.bins() / stacked;
+        let transform = ColumnTransform::new(spectrogram_settings, settings, bins);
+
+        Self {
+            spectrum: vec![S::zero(); front_end.bins()],
+            column: vec![S::zero(); transform.input_len(bins)],
+            front_end,
+            stacked,
+            transform,
+        }
+    }
+
+    /// Number of values in each vector
+    pub fn height(&self) -> usize {
+        self.transform.height() * self.stacked
+    }
+
+    /// Number of columns that will be produced from `n_samples` samples
+    pub fn frames(&self, n_samples: usize) -> usize {
+        self.front_end.frames(n_samples)
+    }
+
+    /// `out` must be exactly `self.frames(samples.len()) * self.height()` long
+    pub fn process(&mut self, samples: &[S], out: &mut [S]) {
+        let height = self.height();
+        let frames = self.frames(samples.len());
+        assert_eq!(out.len(), frames * height);
+
+        let mut scratch = Vec::new();
+
+        for (frame_index, vector) in out.chunks_exact_mut(height).enumerate() {
+            let frame = self.front_end.frame(samples, frame_index, &mut scratch);
+            self.process_frame(frame, vector);
+        }
+
+        self.transform.finish(out, self.stacked);
+    }
+
+    /// The vector produced for a frame of silence, which isn't all zeros for every magnitude scale
+    pub fn silence(&mut self) -> Vec<S> {
+        let mut vector = vec![S::zero(); self.height()];
+        self.process_frame(&[], &mut vector);
+        self.transform.finish(&mut vector, self.stacked);
+
+        vector
+    }
+
+    /// Everything but [`ColumnTransform::finish`] for a single vector
+    fn process_frame(&mut self, frame: &[S], vector: &mut [S]) {
+        let bins = self.front_end.bins() / self.stacked;
+        self.front_end.process_frame(frame, &mut self.spectrum);
+
+        for (spectrum, column) in self
+            .spectrum
+            .chunks_exact(bins)
+            .zip(vector.chunks_exact_mut(self.transform.height()))
+        {
+            self.column[..bins].copy_from_slice(spectrum);
+            self.transform.apply(&self.column, column);
+        }
+    }
+
+    /// Like [`Self::process`], but resizes `out` to fit, reusing its allocation where possible
+    pub fn process_into_vec(&mut self, samples: &[S], out: &mut Vec<S>) {
+        out.resize(self.frames(samples.len()) * self.height(), S::zero());
+
+        self.process(samples, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pleep::{chroma::N_CHROMA, window::WindowFunction};
+
+    const SAMPLE_RATE: usize = 22050;
+
+    /// A major triad on `root`, faded in after a short silence so the first frame only hears it in its long
+    /// window
+    fn triad(root: f64) -> Vec<f32> {
+        let frequencies = [0, 4, 7].map(|semitones| root * 2f64.powf(semitones as f64 / 12.0));
+        let silence = std::iter::repeat_n(0.0, 6144);
+        silence
+            .chain((0..SAMPLE_RATE).map(|i| {
+                let t = i as f64 / SAMPLE_RATE as f64;
+                let fade = (i as f64 / 2048.0).min(1.0);
+                let sum = frequencies
+                    .iter()
+                    .map(|frequency| (2.0 * std::f64::consts::PI * frequency * t).sin())
+                    .sum::<f64>();
+                (fade * fade * sum) as f32
+            }))
+            .collect()
+    }
+
+    fn stacked_chroma(samples: &[f32]) -> (Vec<f32>, usize) {
+        let spectrogram_settings = pleep::spectrogram::Settings {
+            fft_len: 16384,
+            window_len: 8192,
+            fft_overlap: 7168,
+            window_function: WindowFunction::BlackmanHarris,
+            center: false,
+        };
+        let resolutions = Resolutions {
+            extra_window_lens: vec![4096],
+            combine: Combine::Stack,
+        };
+        let settings = FeatureSettings::Chroma {
+            chroma: pleep::chroma::Settings {
+                tuning_frequency: 440.0,
+                min_frequency: 100.0,
+                max_frequency: 4000.0,
+            },
+            input_sample_rate: SAMPLE_RATE,
+        };
+        let mut processor = FeatureProcessor::new(
+            &spectrogram_settings,
+            &resolutions,
+            &settings,
+            &Generator::new(),
+        );
+        let mut out = Vec::new();
+        processor.process_into_vec(samples, &mut out);
+        (out, processor.height())
+    }
+
+    fn max_difference(left: &[f32], right: &[f32]) -> f32 {
+        assert_eq!(left.len(), right.len());
+        left.iter()
+            .zip(right)
+            .map(|(left, right)| (left - right).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn transposed_chroma_matches_pitch_shifted_input() {
+        let root = 440.0 * 2f64.powf(3.0 / 12.0);
+        let (original, height) = stacked_chroma(&triad(root));
+        assert_eq!(height, 2 * N_CHROMA);
+
+        // big enough to wrap a note of the chord around the end of its chroma block
+        for semitones in [5, 7, 11] {
+            let (shifted, _) = stacked_chroma(&triad(root * 2f64.powf(semitones as f64 / 12.0)));
+
+            let mut transposed = shifted.clone();
+            pleep::chroma::transpose(&mut transposed, semitones);
+            let difference = max_difference(&original, &transposed);
+            assert!(difference < 0.2, "shift {semitones}: {difference}");
+
+            // rotating whole stacked vectors moves notes into the short window's block, which heard only silence
+            let mut across_blocks = shifted;
+            across_blocks
+                .chunks_exact_mut(height)
+                .for_each(|vector| vector.rotate_left(semitones));
+            assert!(max_difference(&original, &across_blocks) > 0.5);
+        }
+    }
+}
@@ -1,13 +1,28 @@
 use std::time::Duration;
 
+use crate::{
+    features::{FeatureKind, FeatureSettings, Resolutions},
+    fingerprint::FingerprintIndex,
+};
+
+/// Bytes at the start of every file, used to tell files apart from those written before versioning
+pub const MAGIC: [u8; 4] = *b"plep";
+/// Bumped whenever the layout of the file or the meaning of the stored vectors changes
+pub const VERSION: u32 = 13;
+
 #[derive(Clone)]
 pub struct File {
     pub build_settings: BuildSettings,
     pub segments: Vec<Segment>,
+    /// Landmark hashes of every segment, only present when built with fingerprints enabled
+    pub fingerprints: Option<FingerprintIndex>,
 }
 
 impl File {
     pub fn write_to(&self, buffer: &mut impl std::io::Write) -> Result<(), Error> {
+        buffer.write_all(&MAGIC)?;
+        buffer.write_all(&VERSION.to_le_bytes())?;
+
         self.build_settings.write_to(buffer)?;
 
         buffer.write_all(&(self.segments.len() as u32).to_le_bytes())?;
@@ -16,10 +31,34 @@ impl File {
             segment.write_to(buffer)?;
         }
 
+        match &self.fingerprints {
+            Some(fingerprints) => {
+                buffer.write_all(&1u32.to_le_bytes())?;
+                fingerprints.write_to(buffer)?;
+            }
+            None => buffer.write_all(&0u32.to_le_bytes())?,
+        }
+
         Ok(())
     }
 
     pub fn read_from(reader: &mut impl std::io::Read) -> Result<Self, Error> {
+        let mut magic_buf = [0; 4];
+        reader.read_exact(&mut magic_buf)?;
+        if magic_buf != MAGIC {
+            return Err(Error::InvalidMagic(magic_buf));
+        }
+
+        let mut version_buf = [0; 4];
+        reader.read_exact(&mut version_buf)?;
+        let version = u32::from_le_bytes(version_buf);
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion {
+                found: version,
+                expected: VERSION,
+            });
+        }
+
         let build_settings = BuildSettings::read_from(reader)?;
 
         let mut n_segments_buf = [0; 4];
@@ -29,13 +68,20 @@ impl File {
         let mut segments = Vec::with_capacity(n_segments as usize);
 
         for _ in 0..n_segments {
-            let segment = Segment::read_from(reader, build_settings.spectrogram_height)?;
+            let segment = Segment::read_from(reader, build_settings.vector_length())?;
             segments.push(segment);
         }
 
+        let fingerprints = match read_u32(reader)? {
+            0 => None,
+            1 => Some(FingerprintIndex::read_from(reader)?),
+            other => return Err(Error::InvalidFingerprintFlag(other)),
+        };
+
         Ok(Self {
             build_settings,
             segments,
+            fingerprints,
         })
     }
 }
@@ -43,25 +89,52 @@ impl File {
 #[derive(Debug, Clone)]
 pub struct BuildSettings {
     pub fft_size: u32,
+    pub window_size: u32,
     pub fft_overlap: u32,
+    pub center: bool,
+    /// Window sizes computed alongside `window_size` on the same hop grid
+    pub extra_window_sizes: Vec<u32>,
+    pub resolution_combine: pleep::multires::Combine,
+    /// How multi-channel audio was made mono, queries must use the same so they match
+    pub channel_mode: pleep_audio::ChannelMode,
     pub spectrogram_height: u32,
+    pub spectrogram_min_frequency: u32,
     pub spectrogram_max_frequency: u32,
     pub resample_rate: u32,
     pub resample_chunk_size: u32,
     pub resample_sub_chunks: u32,
     pub log_base: f32,
+    pub frequency_scale: crate::FrequencyScale,
+    pub magnitude_scale: pleep::magnitude::MagnitudeScale,
+    pub log_aggregation: crate::Aggregation,
+    pub window_function: pleep::window::WindowFunction,
+    pub feature: FeatureKind,
 }
 
 impl BuildSettings {
     pub fn write_to(&self, buffer: &mut impl std::io::Write) -> Result<(), Error> {
         buffer.write_all(&self.fft_size.to_le_bytes())?;
+        buffer.write_all(&self.window_size.to_le_bytes())?;
         buffer.write_all(&self.fft_overlap.to_le_bytes())?;
+        buffer.write_all(&(self.center as u32).to_le_bytes())?;
+        buffer.write_all(&(self.extra_window_sizes.len() as u32).to_le_bytes())?;
+        for window_size in &self.extra_window_sizes {
+            buffer.write_all(&window_size.to_le_bytes())?;
+        }
+        buffer.write_all(&write_combine(self.resolution_combine).to_le_bytes())?;
+        write_channel_mode(buffer, self.channel_mode)?;
         buffer.write_all(&self.spectrogram_height.to_le_bytes())?;
+        buffer.write_all(&self.spectrogram_min_frequency.to_le_bytes())?;
         buffer.write_all(&self.spectrogram_max_frequency.to_le_bytes())?;
         buffer.write_all(&self.resample_rate.to_le_bytes())?;
         buffer.write_all(&self.resample_chunk_size.to_le_bytes())?;
         buffer.write_all(&self.resample_sub_chunks.to_le_bytes())?;
         buffer.write_all(&self.log_base.to_le_bytes())?;
+        buffer.write_all(&write_frequency_scale(self.frequency_scale).to_le_bytes())?;
+        write_magnitude_scale(buffer, &self.magnitude_scale)?;
+        buffer.write_all(&write_aggregation(self.log_aggregation).to_le_bytes())?;
+        write_window_function(buffer, &self.window_function)?;
+        write_feature_kind(buffer, &self.feature)?;
 
         Ok(())
     }
@@ -71,14 +144,27 @@ impl BuildSettings {
         reader.read_exact(&mut fft_size_buffer)?;
         let fft_size = u32::from_le_bytes(fft_size_buffer);
 
+        let window_size = read_u32(reader)?;
+
         let mut fft_overlap_buffer = [0; 4];
         reader.read_exact(&mut fft_overlap_buffer)?;
         let fft_overlap = u32::from_le_bytes(fft_overlap_buffer);
 
+        let center = read_u32(reader)? != 0;
+
+        let n_extra_window_sizes = read_u32(reader)?;
+        let extra_window_sizes = (0..n_extra_window_sizes)
+            .map(|_| read_u32(reader))
+            .collect::<Result<Vec<_>, _>>()?;
+        let resolution_combine = read_combine(read_u32(reader)?)?;
+        let channel_mode = read_channel_mode(reader)?;
+
         let mut spectrogram_height_buffer = [0; 4];
         reader.read_exact(&mut spectrogram_height_buffer)?;
         let spectrogram_height = u32::from_le_bytes(spectrogram_height_buffer);
 
+        let spectrogram_min_frequency = read_u32(reader)?;
+
         let mut spectrogram_max_frequency_buffer = [0; 4];
         reader.read_exact(&mut spectrogram_max_frequency_buffer)?;
         let spectrogram_max_frequency = u32::from_le_bytes(spectrogram_max_frequency_buffer);
@@ -99,30 +185,482 @@ impl BuildSettings {
         reader.read_exact(&mut log_base_buffer)?;
         let log_base = f32::from_le_bytes(log_base_buffer);
 
+        let frequency_scale = read_frequency_scale(read_u32(reader)?)?;
+        let magnitude_scale = read_magnitude_scale(reader)?;
+        let log_aggregation = read_aggregation(read_u32(reader)?)?;
+
+        let window_function = read_window_function(reader)?;
+        let feature = read_feature_kind(reader)?;
+
         Ok(Self {
             fft_size,
+            window_size,
             fft_overlap,
+            center,
+            extra_window_sizes,
+            resolution_combine,
+            channel_mode,
             spectrogram_height,
+            spectrogram_min_frequency,
             spectrogram_max_frequency,
             resample_rate,
             resample_chunk_size,
             resample_sub_chunks,
             log_base,
+            frequency_scale,
+            magnitude_scale,
+            log_aggregation,
+            window_function,
+            feature,
         })
     }
+
+    pub fn spectrogram_settings(&self) -> pleep::spectrogram::Settings {
+        pleep::spectrogram::Settings {
+            fft_len: self.fft_size as usize,
+            window_len: self.window_size as usize,
+            fft_overlap: self.fft_overlap as usize,
+            window_function: self.window_function,
+            center: self.center,
+        }
+    }
+
+    /// A generator with the ffts and windows used by these settings already prepared, to be shared by
+    /// every thread processing audio
+    pub fn generator(&self) -> pleep::spectrogram::Generator<f32> {
+        let spectrogram_settings = self.spectrogram_settings();
+
+        self.extra_window_sizes.iter().fold(
+            pleep::spectrogram::Generator::new().with_settings(&spectrogram_settings),
+            |generator, window_size| {
+                generator.with_window(spectrogram_settings.window_function, *window_size as usize)
+            },
+        )
+    }
+
+    pub fn resolutions(&self) -> Resolutions {
+        Resolutions {
+            extra_window_lens: self
+                .extra_window_sizes
+                .iter()
+                .map(|window_size| *window_size as usize)
+                .collect(),
+            combine: self.resolution_combine,
+        }
+    }
+
+    pub fn log_spectrogram_settings(&self) -> crate::LogSpectrogramSettings {
+        crate::LogSpectrogramSettings {
+            height: self.spectrogram_height as usize,
+            min_frequency: self.spectrogram_min_frequency as usize,
+            frequency_cutoff: self.spectrogram_max_frequency as usize,
+            input_sample_rate: self.resample_rate as usize,
+            base: self.log_base,
+            scale: self.frequency_scale,
+            magnitude_scale: self.magnitude_scale,
+            aggregation: self.log_aggregation,
+        }
+    }
+
+    pub fn feature_settings(&self) -> FeatureSettings {
+        match self.feature {
+            FeatureKind::LogSpectrogram => {
+                FeatureSettings::LogSpectrogram(self.log_spectrogram_settings())
+            }
+            FeatureKind::MelSpectrogram {
+                min_frequency,
+                scale,
+            } => FeatureSettings::MelSpectrogram {
+                mel: pleep::mel::Settings {
+                    n_mels: self.spectrogram_height as usize,
+                    min_frequency: min_frequency as f64,
+                    max_frequency: self.spectrogram_max_frequency as f64,
+                    scale,
+                },
+                input_sample_rate: self.resample_rate as usize,
+            },
+            FeatureKind::Mfcc {
+                min_frequency,
+                scale,
+                n_coefficients,
+                delta_order,
+                delta_width,
+            } => FeatureSettings::Mfcc {
+                mfcc: pleep::mfcc::Settings {
+                    mel: pleep::mel::Settings {
+                        n_mels: self.spectrogram_height as usize,
+                        min_frequency: min_frequency as f64,
+                        max_frequency: self.spectrogram_max_frequency as f64,
+                        scale,
+                    },
+                    n_coefficients: n_coefficients as usize,
+                    delta_order: delta_order as usize,
+                    delta_width: delta_width as usize,
+                },
+                input_sample_rate: self.resample_rate as usize,
+            },
+            FeatureKind::Chroma {
+                min_frequency,
+                tuning_frequency,
+            } => FeatureSettings::Chroma {
+                chroma: pleep::chroma::Settings {
+                    tuning_frequency: tuning_frequency as f64,
+                    min_frequency: min_frequency as f64,
+                    max_frequency: self.spectrogram_max_frequency as f64,
+                },
+                input_sample_rate: self.resample_rate as usize,
+            },
+            FeatureKind::ConstantQ {
+                min_frequency,
+                bins_per_octave,
+                n_octaves,
+            } => FeatureSettings::ConstantQ(pleep::cqt::Settings {
+                sample_rate: self.resample_rate as usize,
+                min_frequency: min_frequency as f64,
+                bins_per_octave: bins_per_octave as usize,
+                n_octaves: n_octaves as usize,
+                hop_len: self.spectrogram_settings().hop_len(),
+                window_function: self.window_function,
+            }),
+        }
+    }
+
+    /// Number of values in each vector of a segment
+    pub fn vector_length(&self) -> u32 {
+        let stacked = self.resolutions().stacked(&self.feature_settings()) as u32;
+
+        stacked
+            * match self.feature {
+                FeatureKind::LogSpectrogram | FeatureKind::MelSpectrogram { .. } => {
+                    self.spectrogram_height
+                }
+                FeatureKind::Mfcc {
+                    n_coefficients,
+                    delta_order,
+                    ..
+                } => n_coefficients * (delta_order + 1),
+                FeatureKind::Chroma { .. } => pleep::chroma::N_CHROMA as u32,
+                FeatureKind::ConstantQ {
+                    bins_per_octave,
+                    n_octaves,
+                    ..
+                } => bins_per_octave * n_octaves,
+            }
+    }
+}
+
+fn write_feature_kind(
+    buffer: &mut impl std::io::Write,
+    feature: &FeatureKind,
+) -> Result<(), Error> {
+    let (tag, parameters): (u32, Vec<u32>) = match feature {
+        FeatureKind::LogSpectrogram => (0, vec![]),
+        FeatureKind::MelSpectrogram {
+            min_frequency,
+            scale,
+        } => (1, vec![*min_frequency, write_mel_scale(*scale)]),
+        FeatureKind::Mfcc {
+            min_frequency,
+            scale,
+            n_coefficients,
+            delta_order,
+            delta_width,
+        } => (
+            2,
+            vec![
+                *min_frequency,
+                write_mel_scale(*scale),
+                *n_coefficients,
+                *delta_order,
+                *delta_width,
+            ],
+        ),
+        FeatureKind::Chroma {
+            min_frequency,
+            tuning_frequency,
+        } => (3, vec![*min_frequency, tuning_frequency.to_bits()]),
+        FeatureKind::ConstantQ {
+            min_frequency,
+            bins_per_octave,
+            n_octaves,
+        } => (4, vec![*min_frequency, *bins_per_octave, *n_octaves]),
+    };
+
+    buffer.write_all(&tag.to_le_bytes())?;
+    for parameter in parameters {
+        buffer.write_all(&parameter.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn read_feature_kind(reader: &mut impl std::io::Read) -> Result<FeatureKind, Error> {
+    let tag = read_u32(reader)?;
+
+    let feature = match tag {
+        0 => FeatureKind::LogSpectrogram,
+        1 => FeatureKind::MelSpectrogram {
+            min_frequency: read_u32(reader)?,
+            scale: read_mel_scale(read_u32(reader)?)?,
+        },
+        2 => FeatureKind::Mfcc {
+            min_frequency: read_u32(reader)?,
+            scale: read_mel_scale(read_u32(reader)?)?,
+            n_coefficients: read_u32(reader)?,
+            delta_order: read_u32(reader)?,
+            delta_width: read_u32(reader)?,
+        },
+        3 => FeatureKind::Chroma {
+            min_frequency: read_u32(reader)?,
+            tuning_frequency: f32::from_bits(read_u32(reader)?),
+        },
+        4 => FeatureKind::ConstantQ {
+            min_frequency: read_u32(reader)?,
+            bins_per_octave: read_u32(reader)?,
+            n_octaves: read_u32(reader)?,
+        },
+        other => return Err(Error::InvalidFeatureKind(other)),
+    };
+
+    Ok(feature)
+}
+
+pub(crate) fn read_u32(reader: &mut impl std::io::Read) -> Result<u32, Error> {
+    let mut buffer = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+fn write_mel_scale(scale: pleep::mel::MelScale) -> u32 {
+    match scale {
+        pleep::mel::MelScale::Htk => 0,
+        pleep::mel::MelScale::Slaney => 1,
+    }
+}
+
+fn read_mel_scale(tag: u32) -> Result<pleep::mel::MelScale, Error> {
+    match tag {
+        0 => Ok(pleep::mel::MelScale::Htk),
+        1 => Ok(pleep::mel::MelScale::Slaney),
+        other => Err(Error::InvalidMelScale(other)),
+    }
+}
+
+fn write_frequency_scale(scale: crate::FrequencyScale) -> u32 {
+    match scale {
+        crate::FrequencyScale::Linear => 0,
+        crate::FrequencyScale::Log => 1,
+        crate::FrequencyScale::Mel => 2,
+        crate::FrequencyScale::Bark => 3,
+        crate::FrequencyScale::Erb => 4,
+    }
+}
+
+fn read_frequency_scale(tag: u32) -> Result<crate::FrequencyScale, Error> {
+    match tag {
+        0 => Ok(crate::FrequencyScale::Linear),
+        1 => Ok(crate::FrequencyScale::Log),
+        2 => Ok(crate::FrequencyScale::Mel),
+        3 => Ok(crate::FrequencyScale::Bark),
+        4 => Ok(crate::FrequencyScale::Erb),
+        other => Err(Error::InvalidFrequencyScale(other)),
+    }
+}
+
+fn write_magnitude_scale(
+    buffer: &mut impl std::io::Write,
+    magnitude_scale: &pleep::magnitude::MagnitudeScale,
+) -> Result<(), Error> {
+    use pleep::magnitude::MagnitudeScale;
+
+    let (tag, parameters): (u32, [f32; 2]) = match magnitude_scale {
+        MagnitudeScale::Linear => (0, [0.0, 0.0]),
+        MagnitudeScale::Power => (1, [0.0, 0.0]),
+        MagnitudeScale::Decibels { reference, floor } => (2, [*reference, *floor]),
+        MagnitudeScale::Log1p { gain } => (3, [*gain, 0.0]),
+    };
+
+    buffer.write_all(&tag.to_le_bytes())?;
+    for parameter in parameters {
+        buffer.write_all(&parameter.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn read_magnitude_scale(
+    reader: &mut impl std::io::Read,
+) -> Result<pleep::magnitude::MagnitudeScale, Error> {
+    use pleep::magnitude::MagnitudeScale;
+
+    let tag = read_u32(reader)?;
+    let first = f32::from_bits(read_u32(reader)?);
+    let second = f32::from_bits(read_u32(reader)?);
+
+    let magnitude_scale = match tag {
+        0 => MagnitudeScale::Linear,
+        1 => MagnitudeScale::Power,
+        2 => MagnitudeScale::Decibels {
+            reference: first,
+            floor: second,
+        },
+        3 => MagnitudeScale::Log1p { gain: first },
+        other => return Err(Error::InvalidMagnitudeScale(other)),
+    };
+
+    Ok(magnitude_scale)
+}
+
+fn write_combine(combine: pleep::multires::Combine) -> u32 {
+    match combine {
+        pleep::multires::Combine::Stack => 0,
+        pleep::multires::Combine::Mean => 1,
+    }
+}
+
+fn read_combine(tag: u32) -> Result<pleep::multires::Combine, Error> {
+    match tag {
+        0 => Ok(pleep::multires::Combine::Stack),
+        1 => Ok(pleep::multires::Combine::Mean),
+        other => Err(Error::InvalidResolutionCombine(other)),
+    }
+}
+
+fn write_channel_mode(
+    buffer: &mut impl std::io::Write,
+    channel_mode: pleep_audio::ChannelMode,
+) -> Result<(), Error> {
+    use pleep_audio::ChannelMode;
+
+    let (tag, channel): (u32, u32) = match channel_mode {
+        ChannelMode::Average => (0, 0),
+        ChannelMode::Channel(channel) => (1, channel as u32),
+        ChannelMode::Mid => (2, 0),
+        ChannelMode::Side => (3, 0),
+        ChannelMode::MaxEnergy => (4, 0),
+    };
+
+    buffer.write_all(&tag.to_le_bytes())?;
+    buffer.write_all(&channel.to_le_bytes())?;
+
+    Ok(())
+}
+
+fn read_channel_mode(reader: &mut impl std::io::Read) -> Result<pleep_audio::ChannelMode, Error> {
+    use pleep_audio::ChannelMode;
+
+    let tag = read_u32(reader)?;
+    let channel = read_u32(reader)?;
+
+    let channel_mode = match tag {
+        0 => ChannelMode::Average,
+        1 => ChannelMode::Channel(channel as usize),
+        2 => ChannelMode::Mid,
+        3 => ChannelMode::Side,
+        4 => ChannelMode::MaxEnergy,
+        other => return Err(Error::InvalidChannelMode(other)),
+    };
+
+    Ok(channel_mode)
+}
+
+fn write_aggregation(aggregation: crate::Aggregation) -> u32 {
+    match aggregation {
+        crate::Aggregation::Max => 0,
+        crate::Aggregation::Mean => 1,
+        crate::Aggregation::Rms => 2,
+        crate::Aggregation::EnergySum => 3,
+        crate::Aggregation::Interpolate => 4,
+    }
+}
+
+fn read_aggregation(tag: u32) -> Result<crate::Aggregation, Error> {
+    match tag {
+        0 => Ok(crate::Aggregation::Max),
+        1 => Ok(crate::Aggregation::Mean),
+        2 => Ok(crate::Aggregation::Rms),
+        3 => Ok(crate::Aggregation::EnergySum),
+        4 => Ok(crate::Aggregation::Interpolate),
+        other => Err(Error::InvalidAggregation(other)),
+    }
+}
+
+fn write_window_function(
+    buffer: &mut impl std::io::Write,
+    window_function: &pleep::window::WindowFunction,
+) -> Result<(), Error> {
+    use pleep::window::WindowFunction;
+
+    let (tag, parameter): (u32, f32) = match window_function {
+        WindowFunction::Hann => (0, 0.0),
+        WindowFunction::Hamming => (1, 0.0),
+        WindowFunction::Blackman => (2, 0.0),
+        WindowFunction::BlackmanHarris => (3, 0.0),
+        WindowFunction::Kaiser { beta } => (4, *beta),
+        WindowFunction::Rectangular => (5, 0.0),
+        WindowFunction::FlatTop => (6, 0.0),
+    };
+
+    buffer.write_all(&tag.to_le_bytes())?;
+    buffer.write_all(&parameter.to_le_bytes())?;
+
+    Ok(())
+}
+
+fn read_window_function(
+    reader: &mut impl std::io::Read,
+) -> Result<pleep::window::WindowFunction, Error> {
+    use pleep::window::WindowFunction;
+
+    let mut tag_buffer = [0; 4];
+    reader.read_exact(&mut tag_buffer)?;
+    let tag = u32::from_le_bytes(tag_buffer);
+
+    let mut parameter_buffer = [0; 4];
+    reader.read_exact(&mut parameter_buffer)?;
+    let parameter = f32::from_le_bytes(parameter_buffer);
+
+    let window_function = match tag {
+        0 => WindowFunction::Hann,
+        1 => WindowFunction::Hamming,
+        2 => WindowFunction::Blackman,
+        3 => WindowFunction::BlackmanHarris,
+        4 => WindowFunction::Kaiser { beta: parameter },
+        5 => WindowFunction::Rectangular,
+        6 => WindowFunction::FlatTop,
+        other => return Err(Error::InvalidWindowFunction(other)),
+    };
+
+    Ok(window_function)
 }
 
 impl From<crate::cli::Options> for BuildSettings {
     fn from(value: crate::cli::Options) -> Self {
         Self {
             fft_size: value.spectrogram.fft_size as u32,
-            fft_overlap: value.spectrogram.fft_overlap as u32,
+            window_size: value.spectrogram.window_len() as u32,
+            fft_overlap: value.spectrogram.fft_overlap() as u32,
+            center: value.spectrogram.center,
+            extra_window_sizes: value
+                .spectrogram
+                .extra_window_sizes
+                .iter()
+                .map(|window_size| *window_size as u32)
+                .collect(),
+            resolution_combine: value.spectrogram.resolution_combine(),
+            channel_mode: value.channels.channel_mode(),
             spectrogram_height: value.log_settings.height as u32,
+            spectrogram_min_frequency: value.log_settings.min_frequency as u32,
             spectrogram_max_frequency: value.log_settings.max_frequency as u32,
             resample_rate: value.resampler.resample_rate as u32,
             resample_chunk_size: value.resampler.chunk_size as u32,
             resample_sub_chunks: value.resampler.sub_chunks as u32,
             log_base: value.log_settings.log_base,
+            frequency_scale: value.log_settings.frequency_scale,
+            magnitude_scale: value.log_settings.magnitude_scale(),
+            log_aggregation: value.log_settings.log_aggregation,
+            window_function: value.spectrogram.window_function(),
+            feature: value.features.feature_kind(),
         }
     }
 }
@@ -197,4 +735,26 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("failed to read utf8: {0:?}")]
     FromUtf8(#[from] std::string::FromUtf8Error),
+    #[error("invalid magic bytes {0:?}, file was likely built by an older version of pleep-build")]
+    InvalidMagic([u8; 4]),
+    #[error("unsupported file version {found}, expected {expected}")]
+    UnsupportedVersion { found: u32, expected: u32 },
+    #[error("unknown window function id {0}")]
+    InvalidWindowFunction(u32),
+    #[error("unknown feature kind id {0}")]
+    InvalidFeatureKind(u32),
+    #[error("unknown mel scale id {0}")]
+    InvalidMelScale(u32),
+    #[error("unknown frequency scale id {0}")]
+    InvalidFrequencyScale(u32),
+    #[error("unknown magnitude scale id {0}")]
+    InvalidMagnitudeScale(u32),
+    #[error("unknown resolution combine id {0}")]
+    InvalidResolutionCombine(u32),
+    #[error("unknown channel mode id {0}")]
+    InvalidChannelMode(u32),
+    #[error("unknown log aggregation id {0}")]
+    InvalidAggregation(u32),
+    #[error("invalid fingerprint index flag {0}")]
+    InvalidFingerprintFlag(u32),
 }
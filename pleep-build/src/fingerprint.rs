@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use pleep::{
+    landmarks::{self, Landmark},
+    peaks,
+};
+use rayon::prelude::*;
+
+use crate::file::{read_u32, Error, Segment};
+
+/// Where a landmark hash was seen in the flat file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Posting {
+    /// Index of the segment in [`crate::file::File::segments`]
+    pub segment: u32,
+    /// Vector the landmark's anchor peak is in
+    pub frame: u32,
+}
+
+/// Inverted index from landmark hashes to every segment and vector they occur at
+#[derive(Debug, Clone)]
+pub struct FingerprintIndex {
+    pub peaks: peaks::Settings,
+    pub landmarks: landmarks::Settings,
+    pub postings: HashMap<u32, Vec<Posting>>,
+}
+
+impl FingerprintIndex {
+    pub fn build(
+        segments: &[Segment],
+        peaks: peaks::Settings,
+        landmarks: landmarks::Settings,
+    ) -> Self {
+        let mut index = Self {
+            peaks,
+            landmarks,
+            postings: HashMap::new(),
+        };
+
+        let segment_landmarks = segments
+            .par_iter()
+            .map(|segment| index.landmarks_for(&segment.vectors))
+            .collect::<Vec<_>>();
+
+        for (segment, landmarks) in segment_landmarks.into_iter().enumerate() {
+            for landmark in landmarks {
+                index
+                    .postings
+                    .entry(landmark.hash)
+                    .or_default()
+                    .push(Posting {
+                        segment: segment as u32,
+                        frame: landmark.frame,
+                    });
+            }
+        }
+
+        index
+    }
+
+    /// Finds the landmarks of `vectors` using the same settings the index was built with
+    pub fn landmarks_for<C: AsRef<[f32]>>(&self, vectors: &[C]) -> Vec<Landmark> {
+        let peaks = peaks::find_peaks(vectors, &self.peaks);
+        landmarks::find_landmarks(&peaks, &self.landmarks)
+    }
+
+    pub fn get(&self, hash: u32) -> &[Posting] {
+        self.postings.get(&hash).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn write_to(&self, buffer: &mut impl std::io::Write) -> Result<(), Error> {
+        buffer.write_all(&(self.peaks.time_radius as u32).to_le_bytes())?;
+        buffer.write_all(&(self.peaks.frequency_radius as u32).to_le_bytes())?;
+        buffer.write_all(&self.peaks.threshold.to_le_bytes())?;
+        buffer.write_all(&(self.peaks.n_bands as u32).to_le_bytes())?;
+        buffer.write_all(&(self.peaks.max_peaks_per_band as u32).to_le_bytes())?;
+
+        buffer.write_all(&(self.landmarks.fan_out as u32).to_le_bytes())?;
+        buffer.write_all(&(self.landmarks.min_time_delta as u32).to_le_bytes())?;
+        buffer.write_all(&(self.landmarks.max_time_delta as u32).to_le_bytes())?;
+        buffer.write_all(&(self.landmarks.max_frequency_delta as u32).to_le_bytes())?;
+
+        // sorted so the same input always produces the same file
+        let mut hashes = self.postings.keys().copied().collect::<Vec<_>>();
+        hashes.sort_unstable();
+
+        buffer.write_all(&(hashes.len() as u32).to_le_bytes())?;
+
+        for hash in hashes {
+            let postings = &self.postings[&hash];
+
+            buffer.write_all(&hash.to_le_bytes())?;
+            buffer.write_all(&(postings.len() as u32).to_le_bytes())?;
+
+            for posting in postings {
+                buffer.write_all(&posting.segment.to_le_bytes())?;
+                buffer.write_all(&posting.frame.to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from(reader: &mut impl std::io::Read) -> Result<Self, Error> {
+        let time_radius = read_u32(reader)? as usize;
+        let frequency_radius = read_u32(reader)? as usize;
+
+        let mut threshold_buffer = [0; 8];
+        reader.read_exact(&mut threshold_buffer)?;
+        let threshold = f64::from_le_bytes(threshold_buffer);
+
+        let peaks = peaks::Settings {
+            time_radius,
+            frequency_radius,
+            threshold,
+            n_bands: read_u32(reader)? as usize,
+            max_peaks_per_band: read_u32(reader)? as usize,
+        };
+
+        let landmarks = landmarks::Settings {
+            fan_out: read_u32(reader)? as usize,
+            min_time_delta: read_u32(reader)? as usize,
+            max_time_delta: read_u32(reader)? as usize,
+            max_frequency_delta: read_u32(reader)? as usize,
+        };
+
+        let n_hashes = read_u32(reader)?;
+        let mut postings = HashMap::with_capacity(n_hashes as usize);
+
+        for _ in 0..n_hashes {
+            let hash = read_u32(reader)?;
+            let n_postings = read_u32(reader)?;
+
+            let mut hash_postings = Vec::with_capacity(n_postings as usize);
+            for _ in 0..n_postings {
+                hash_postings.push(Posting {
+                    segment: read_u32(reader)?,
+                    frame: read_u32(reader)?,
+                });
+            }
+
+            postings.insert(hash, hash_postings);
+        }
+
+        Ok(Self {
+            peaks,
+            landmarks,
+            postings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_round_trips_through_a_file() {
+        let mut index = FingerprintIndex {
+            peaks: peaks::Settings {
+                time_radius: 3,
+                frequency_radius: 4,
+                threshold: 0.25,
+                n_bands: 6,
+                max_peaks_per_band: 5,
+            },
+            landmarks: landmarks::Settings {
+                fan_out: 7,
+                min_time_delta: 1,
+                max_time_delta: 64,
+                max_frequency_delta: 32,
+            },
+            postings: HashMap::new(),
+        };
+        for (hash, segment, frame) in [(9, 0, 12), (3, 1, 0), (9, 2, 40), (u32::MAX, 0, 7)] {
+            index
+                .postings
+                .entry(hash)
+                .or_default()
+                .push(Posting { segment, frame });
+        }
+
+        let mut buffer = Vec::new();
+        index.write_to(&mut buffer).unwrap();
+        let read = FingerprintIndex::read_from(&mut buffer.as_slice()).unwrap();
+
+        assert_eq!(read.peaks, index.peaks);
+        assert_eq!(read.landmarks, index.landmarks);
+        assert_eq!(read.postings, index.postings);
+    }
+}
@@ -1,12 +1,12 @@
-#![feature(array_windows)]
-
 use std::path::PathBuf;
 
-use pleep::spectrogram::SpectrogramIterator;
+use pleep::{magnitude::MagnitudeScale, spectrogram::SpectrogramIterator};
 use tracing::{debug, instrument, warn};
 
 pub mod cli;
+pub mod features;
 pub mod file;
+pub mod fingerprint;
 
 #[instrument(level = "trace", err(level = "debug"))]
 pub fn get_files_in_directory(directory: &PathBuf) -> Result<Vec<PathBuf>, std::io::Error> {
@@ -53,41 +53,228 @@ pub fn make_log<S: pleep::spectrogram::Float>(
     values: &[S],
     out_height: usize,
     base: f32,
+    aggregation: Aggregation,
 ) -> Vec<S> {
     let mut new = vec![S::zero(); out_height];
 
+    LogResampler::new(log_bin_edges(values.len(), out_height, base), aggregation)
+        .apply_into(values, &mut new);
+
+    new
+}
+
+/// Positions in the linear values that each row of the log output spans between, `out_height + 1` long
+pub fn log_bin_edges(n_values: usize, out_height: usize, base: f32) -> Vec<f64> {
     let base = base as f64;
 
-    for (index, [last_index, next_index]) in (0..=out_height)
+    (0..=out_height)
         .map(|index| {
             let frac = index as f64 / out_height as f64;
-            ((base.powf(frac) - 1.0) / (base - 1.0) * values.len() as f64) as usize
+            (base.powf(frac) - 1.0) / (base - 1.0) * n_values as f64
         })
-        .collect::<Vec<_>>()
-        .array_windows()
-        .enumerate()
-    {
-        // TODO: decide on the best way to find a value for a pixel
-        let to_average = &values[*last_index..*next_index];
-        // let average = average(to_average);
-
-        // new[index] = average;
-        new[index] = *to_average
-            .iter()
-            .max_by(|l, r| l.partial_cmp(r).unwrap_or(std::cmp::Ordering::Less))
-            .unwrap();
-        // new[index] = values[*last_index];
+        .collect()
+}
+
+/// How rows of a log spectrogram are spaced in frequency
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FrequencyScale {
+    /// Equally spaced in hz
+    Linear,
+    /// Spaced by `(base^frac - 1) / (base - 1)`, using the log base
+    #[default]
+    Log,
+    /// Equally spaced in htk mels
+    Mel,
+    /// Equally spaced in barks (Traunmüller)
+    Bark,
+    /// Equally spaced in equivalent rectangular bandwidths (Glasberg & Moore)
+    Erb,
+}
+
+impl FrequencyScale {
+    /// Frequencies that each of the `height` rows spans between, `height + 1` long
+    pub fn edges(
+        &self,
+        min_frequency: f64,
+        max_frequency: f64,
+        height: usize,
+        base: f32,
+    ) -> Vec<f64> {
+        assert!(
+            min_frequency < max_frequency,
+            "minimum frequency ({min_frequency}hz) must be below the maximum frequency ({max_frequency}hz)"
+        );
+
+        let base = base as f64;
+        let (low, high) = (self.warp(min_frequency), self.warp(max_frequency));
+
+        (0..=height)
+            .map(|index| {
+                let frac = index as f64 / height as f64;
+
+                match self {
+                    FrequencyScale::Log => {
+                        min_frequency
+                            + (max_frequency - min_frequency) * (base.powf(frac) - 1.0)
+                                / (base - 1.0)
+                    }
+                    _ => self.unwarp(low + (high - low) * frac),
+                }
+            })
+            .collect()
     }
 
-    new
+    /// Converts hz onto this scale, the log scale is relative to the range so it is left as hz
+    fn warp(&self, hz: f64) -> f64 {
+        match self {
+            FrequencyScale::Linear | FrequencyScale::Log => hz,
+            FrequencyScale::Mel => pleep::mel::MelScale::Htk.hz_to_mel(hz),
+            FrequencyScale::Bark => 26.81 * hz / (1960.0 + hz) - 0.53,
+            FrequencyScale::Erb => 21.4 * (1.0 + 0.00437 * hz).log10(),
+        }
+    }
+
+    fn unwarp(&self, value: f64) -> f64 {
+        match self {
+            FrequencyScale::Linear | FrequencyScale::Log => value,
+            FrequencyScale::Mel => pleep::mel::MelScale::Htk.mel_to_hz(value),
+            FrequencyScale::Bark => 1960.0 * (value + 0.53) / (26.28 - value),
+            FrequencyScale::Erb => (10f64.powf(value / 21.4) - 1.0) / 0.00437,
+        }
+    }
+}
+
+/// How the linear values covered by a row of a log spectrogram are combined into one value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Aggregation {
+    /// The loudest value
+    #[default]
+    Max,
+    Mean,
+    /// Root mean square of the values
+    Rms,
+    /// Sum of the squared values
+    EnergySum,
+    /// Linearly interpolate the values at the centre of the row
+    Interpolate,
+}
+
+/// Resamples linear columns onto rows that are spaced logarithmically in frequency
+#[derive(Debug, Clone)]
+pub struct LogResampler {
+    edges: Vec<f64>,
+    aggregation: Aggregation,
 }
 
-// TODO: remove if unused in ^^^
-// fn average<S: pleep::spectrogram::Float>(values: &[S]) -> S {
-//     let count = values.len();
-//
-//     S::from(values.iter().map(|v| v.to_f64().unwrap()).sum::<f64>() / count as f64).unwrap()
-// }
+impl LogResampler {
+    /// Creates a resampler whose rows span between consecutive positions in `edges`, measured in linear values
+    pub fn new(edges: Vec<f64>, aggregation: Aggregation) -> Self {
+        assert!(edges.len() >= 2, "need at least one row");
+
+        Self { edges, aggregation }
+    }
+
+    /// Creates a resampler for columns produced with `spectrogram_settings`
+    pub fn from_settings(
+        spectrogram_settings: &pleep::spectrogram::Settings,
+        settings: &LogSpectrogramSettings,
+    ) -> Self {
+        let edges = settings
+            .scale
+            .edges(
+                settings.min_frequency as f64,
+                settings.frequency_cutoff as f64,
+                settings.height,
+                settings.base,
+            )
+            .into_iter()
+            .map(|frequency| {
+                pleep::spectrogram::get_bin_for_frequency(
+                    frequency,
+                    settings.input_sample_rate,
+                    spectrogram_settings.fft_len,
+                )
+            })
+            .collect();
+
+        Self::new(edges, settings.aggregation)
+    }
+
+    pub fn height(&self) -> usize {
+        self.edges.len() - 1
+    }
+
+    /// Writes the rows for `values` into `out`, which must be [`Self::height`] long.
+    ///
+    /// Rows narrower than a single value (common for low rows) are interpolated whatever the aggregation.
+    pub fn apply_into<S: pleep::spectrogram::Float>(&self, values: &[S], out: &mut [S]) {
+        assert_eq!(out.len(), self.height());
+
+        for (value, [low, high]) in out.iter_mut().zip(self.edges.array_windows()) {
+            let range =
+                &values[(*low as usize).min(values.len())..(*high as usize).min(values.len())];
+
+            *value = match self.aggregation {
+                _ if range.is_empty() => interpolate(values, (low + high) / 2.0),
+                Aggregation::Interpolate => interpolate(values, (low + high) / 2.0),
+                Aggregation::Max => range.iter().copied().fold(S::neg_infinity(), S::max),
+                Aggregation::Mean => {
+                    range
+                        .iter()
+                        .copied()
+                        .fold(S::zero(), |acc, value| acc + value)
+                        / S::from(range.len()).unwrap()
+                }
+                Aggregation::Rms => (energy(range) / S::from(range.len()).unwrap()).sqrt(),
+                Aggregation::EnergySum => energy(range),
+            };
+        }
+    }
+
+    /// Spreads rows back over `out` linear values, the rough inverse of [`Self::apply_into`].
+    ///
+    /// Each value takes the row its centre falls in, and values outside every row are zero.
+    pub fn invert_into<S: pleep::spectrogram::Float>(&self, rows: &[S], out: &mut [S]) {
+        assert_eq!(rows.len(), self.height());
+
+        out.fill(S::zero());
+
+        for (row, [low, high]) in rows.iter().zip(self.edges.array_windows()) {
+            let first = (low - 0.5).ceil().max(0.0) as usize;
+            let last = ((high - 0.5).ceil().max(0.0) as usize).min(out.len());
+
+            // an energy sum covers every value in the row, so share it back out between them
+            let value = match self.aggregation {
+                Aggregation::EnergySum => (*row / S::from((high - low).max(1.0)).unwrap()).sqrt(),
+                _ => *row,
+            };
+
+            for linear in out.iter_mut().take(last).skip(first) {
+                *linear = value;
+            }
+        }
+    }
+}
+
+fn energy<S: pleep::spectrogram::Float>(values: &[S]) -> S {
+    values
+        .iter()
+        .fold(S::zero(), |acc, value| acc + *value * *value)
+}
+
+/// Linearly interpolates `values` at a fractional `position`, where value `n` covers `n..n + 1`
+fn interpolate<S: pleep::spectrogram::Float>(values: &[S], position: f64) -> S {
+    let Some(last) = values.len().checked_sub(1) else {
+        return S::zero();
+    };
+
+    let position = (position - 0.5).clamp(0.0, last as f64);
+    let index = position as usize;
+    let next = (index + 1).min(last);
+    let frac = S::from(position - index as f64).unwrap();
+
+    values[index] + (values[next] - values[index]) * frac
+}
 
 #[instrument(skip(samples), level = "trace")]
 pub fn generate_log_spectrogram<S: pleep::spectrogram::Float, I: Iterator<Item = S>>(
@@ -102,34 +289,87 @@ pub fn generate_log_spectrogram<S: pleep::spectrogram::Float, I: Iterator<Item =
         &spectrogram_generator,
     );
 
-    let cutoff_bin = pleep::spectrogram::get_bin_for_frequency(
+    let cutoff_bin = get_cutoff_bin(spectrogram_settings, settings);
+
+    LogSpectrogramIterator::new(
+        spectrogram,
+        cutoff_bin,
+        settings.magnitude_scale,
+        LogResampler::from_settings(spectrogram_settings, settings),
+    )
+}
+
+/// Estimates the samples that a log spectrogram came from, using `iterations` rounds of griffin-lim
+pub fn reconstruct_log_spectrogram<C: AsRef<[f32]>>(
+    vectors: &[C],
+    spectrogram_settings: &pleep::spectrogram::Settings,
+    settings: &LogSpectrogramSettings,
+    iterations: usize,
+) -> Vec<f32> {
+    let generator = pleep::spectrogram::Generator::new();
+    let resampler = LogResampler::from_settings(spectrogram_settings, settings);
+    let cutoff_bin = get_cutoff_bin(spectrogram_settings, settings);
+    let bins = spectrogram_settings.fft_len / 2;
+
+    let mut magnitudes = vec![0.0; vectors.len() * bins];
+    for (vector, column) in vectors.iter().zip(magnitudes.chunks_exact_mut(bins)) {
+        // stacked vectors start with the main window's column
+        let linear = &mut column[..cutoff_bin.min(bins)];
+        resampler.invert_into(&vector.as_ref()[..resampler.height()], linear);
+
+        for value in linear.iter_mut() {
+            *value = settings.magnitude_scale.invert(*value);
+        }
+    }
+
+    let n_samples = match vectors.len() {
+        0 => 0,
+        frames => ((frames - 1) * spectrogram_settings.hop_len() + spectrogram_settings.window_len)
+            .saturating_sub(spectrogram_settings.start_padding()),
+    };
+
+    pleep::inverse::griffin_lim(
+        &magnitudes,
+        n_samples,
+        spectrogram_settings,
+        iterations,
+        &generator,
+    )
+}
+
+pub(crate) fn get_cutoff_bin(
+    spectrogram_settings: &pleep::spectrogram::Settings,
+    settings: &LogSpectrogramSettings,
+) -> usize {
+    pleep::spectrogram::get_bin_for_frequency(
         settings.frequency_cutoff as f64,
         settings.input_sample_rate,
         spectrogram_settings.fft_len,
-    );
-    let cutoff_bin = cutoff_bin as usize;
-
-    LogSpectrogramIterator::new(spectrogram, settings.height, cutoff_bin, settings.base)
+    ) as usize
 }
 
 pub struct LogSpectrogramIterator<S: pleep::spectrogram::Float, I: Iterator<Item = S>> {
     inner: SpectrogramIterator<S, I>,
     cutoff_bin: usize,
-    height: usize,
-    base: f32,
+    magnitude_scale: MagnitudeScale,
+    resampler: LogResampler,
 }
 
 impl<S: pleep::spectrogram::Float, I: Iterator<Item = S>> LogSpectrogramIterator<S, I> {
-    pub fn new(spectrogram: SpectrogramIterator<S, I>, height: usize, cutoff_bin: usize, base: f32) -> Self {
+    pub fn new(
+        spectrogram: SpectrogramIterator<S, I>,
+        cutoff_bin: usize,
+        magnitude_scale: MagnitudeScale,
+        resampler: LogResampler,
+    ) -> Self {
         Self {
             inner: spectrogram,
-            height,
             cutoff_bin,
-            base,
+            magnitude_scale,
+            resampler,
         }
     }
 
-    #[expect(clippy::misnamed_getters, reason = "this takes an input of height `height` and changes it to be `cutoff_bin`, so that is the new height")]
     pub fn height(&self) -> usize {
         self.cutoff_bin
     }
@@ -143,8 +383,12 @@ impl<S: pleep::spectrogram::Float, I: Iterator<Item = S>> Iterator
     fn next(&mut self) -> Option<Self::Item> {
         self.inner.next().map(|mut col| {
             col.resize(self.cutoff_bin, S::zero());
+            self.magnitude_scale.apply_in_place(&mut col);
+
+            let mut new = vec![S::zero(); self.resampler.height()];
+            self.resampler.apply_into(&col, &mut new);
 
-            make_log(&col, self.height, self.base)
+            new
         })
     }
 }
@@ -152,7 +396,80 @@ impl<S: pleep::spectrogram::Float, I: Iterator<Item = S>> Iterator
 #[derive(Debug, Clone)]
 pub struct LogSpectrogramSettings {
     pub height: usize,
+    pub min_frequency: usize,
     pub frequency_cutoff: usize,
     pub input_sample_rate: usize,
     pub base: f32,
+    pub scale: FrequencyScale,
+    pub magnitude_scale: MagnitudeScale,
+    pub aggregation: Aggregation,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AGGREGATIONS: [Aggregation; 5] = [
+        Aggregation::Max,
+        Aggregation::Mean,
+        Aggregation::Rms,
+        Aggregation::EnergySum,
+        Aggregation::Interpolate,
+    ];
+
+    /// Few values and many rows, so the low rows are much narrower than a single value
+    const N_VALUES: usize = 12;
+    const HEIGHT: usize = 300;
+    const BASE: f32 = 1000.0;
+
+    fn values() -> Vec<f32> {
+        (0..N_VALUES).map(|index| (index * 7 % 11) as f32).collect()
+    }
+
+    #[test]
+    fn narrow_rows_are_finite_for_every_aggregation() {
+        let edges = log_bin_edges(N_VALUES, HEIGHT, BASE);
+        assert!(edges[1] - edges[0] < 1.0);
+
+        for aggregation in AGGREGATIONS {
+            let rows = make_log(&values(), HEIGHT, BASE, aggregation);
+
+            assert_eq!(rows.len(), HEIGHT);
+            assert!(
+                rows.iter().all(|row| row.is_finite()),
+                "{aggregation:?} gave {rows:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn interpolated_rows_are_monotone_between_neighbouring_values() {
+        let values = values();
+        let rows = make_log(&values, HEIGHT, BASE, Aggregation::Interpolate);
+        let edges = log_bin_edges(N_VALUES, HEIGHT, BASE);
+
+        for (index, pair) in rows.windows(2).enumerate() {
+            // value `n` is centred on `n + 0.5`, so this is the value each row's centre comes after
+            let [before, after] = [index, index + 1].map(|row| {
+                let centre = (edges[row] + edges[row + 1]) / 2.0;
+                ((centre - 0.5).max(0.0) as usize).min(N_VALUES - 1)
+            });
+            if before != after || before == N_VALUES - 1 {
+                continue;
+            }
+
+            let (low, high) = (values[before], values[before + 1]);
+            let step = pair[1] - pair[0];
+
+            assert!(
+                pair.iter()
+                    .all(|row| *row >= low.min(high) && *row <= low.max(high)),
+                "rows {pair:?} leave the range of values {low} and {high}"
+            );
+            assert!(
+                step * (high - low) >= 0.0,
+                "rows {pair:?} move away from {high} on their way from {low}"
+            );
+        }
+    }
 }
@@ -1,6 +1,6 @@
-use clap::Parser;
-use pleep_build::cli::{file_to_log_spectrogram, Options};
-use tracing::{debug, info};
+use clap::{CommandFactory, FromArgMatches};
+use pleep_build::cli::{file_to_features, DecodeSettings, Options};
+use tracing::{debug, error, info, warn};
 
 fn main() {
     {
@@ -15,7 +15,22 @@ fn main() {
             .init();
     }
 
-    let options = Options::parse();
+    let formats = pleep_audio::compiled_formats().join(", ");
+    let options = Options::from_arg_matches(
+        &Options::command()
+            .after_help(format!("Supported audio formats: {formats}"))
+            .get_matches(),
+    )
+    .unwrap_or_else(|error| error.exit());
+    let time_range = options
+        .time_range
+        .time_range()
+        .unwrap_or_else(|error| error.format(&mut Options::command()).exit());
+    options
+        .validate()
+        .unwrap_or_else(|error| error.format(&mut Options::command()).exit());
+    info!(formats, "compiled with support for");
+
     let resample_settings: pleep_audio::ResampleSettings = options.clone().resampler.into();
     let spectrogram_settings: pleep::spectrogram::Settings = options.clone().spectrogram.into();
 
@@ -32,7 +47,17 @@ fn main() {
     let mut out_file_values = pleep_build::file::File {
         build_settings: options.clone().into(),
         segments: Vec::new(),
+        fingerprints: None,
+    };
+
+    let feature_settings = out_file_values.build_settings.feature_settings();
+    let resolutions = out_file_values.build_settings.resolutions();
+    let decode_settings = DecodeSettings {
+        track: options.tracks.track_selection(),
+        time_range,
+        channel_mode: out_file_values.build_settings.channel_mode,
     };
+    let generator = out_file_values.build_settings.generator();
 
     let (send, recv) = crossbeam::channel::unbounded();
 
@@ -51,41 +76,93 @@ fn main() {
 
             let spectrogram_settings = spectrogram_settings.clone();
             let resample_settings = resample_settings.clone();
-            let log_settings = options.log_settings.clone();
+            let feature_settings = feature_settings.clone();
+            let resolutions = resolutions.clone();
+            let decode_settings = decode_settings.clone();
+            let generator = generator.clone();
             let sender = send.clone();
 
             s.spawn(move |_s| {
                 info!(path=?file, "processing file");
-                let (audio_duration, log_spectrogram) = file_to_log_spectrogram(
+                let features = file_to_features(
                     &file,
+                    &decode_settings,
                     &spectrogram_settings,
+                    &resolutions,
                     &resample_settings,
-                    &log_settings,
+                    &feature_settings,
+                    &generator,
                 );
+                let (audio_duration, vectors, health) = match features {
+                    Ok(features) => features,
+                    Err(error) => {
+                        error!(path=?file, %error, "skipping file that failed to load");
+                        return;
+                    }
+                };
 
                 let segment = pleep_build::file::Segment {
                     title: file.to_string_lossy().to_string(),
-                    vectors: log_spectrogram.collect(),
+                    vectors,
                     duration: audio_duration,
                 };
 
-                sender.send(segment).expect("failed to send to mpsc");
+                sender
+                    .send((segment, health))
+                    .expect("failed to send to mpsc");
             });
         }
     });
 
     info!("all subtasks finished");
 
-    while let Ok(segment) = recv.recv() {
+    let mut unclean_files = 0;
+    let mut total_health = pleep_audio::DecodeHealth::default();
+
+    while let Ok((segment, health)) = recv.recv() {
+        if !health.is_clean() {
+            warn!(
+                path = segment.title,
+                packets_skipped = health.packets_skipped,
+                samples_lost = health.samples_lost,
+                resets = health.resets,
+                "file did not decode cleanly"
+            );
+
+            unclean_files += 1;
+        }
+
+        total_health.packets_skipped += health.packets_skipped;
+        total_health.samples_lost += health.samples_lost;
+        total_health.resets += health.resets;
+
         out_file_values.segments.push(segment);
     }
 
+    info!(
+        unclean_files,
+        packets_skipped = total_health.packets_skipped,
+        samples_lost = total_health.samples_lost,
+        resets = total_health.resets,
+        "decode health"
+    );
+
     info!("sorting segments");
 
     out_file_values
         .segments
         .sort_by_key(|segment| segment.title.clone());
 
+    if options.fingerprints.fingerprints {
+        info!("building fingerprint index");
+
+        out_file_values.fingerprints = Some(pleep_build::fingerprint::FingerprintIndex::build(
+            &out_file_values.segments,
+            options.fingerprints.peak_settings(),
+            options.fingerprints.landmark_settings(),
+        ));
+    }
+
     info!("saving file");
 
     out_file_values
@@ -0,0 +1,459 @@
+use std::sync::OnceLock;
+
+/// One implementation of every distance kernel, all using the same instruction set.
+///
+/// The functions are only safe to call once the cpu is known to support that instruction set, and
+/// with slices of equal length.
+struct Kernels {
+    name: &'static str,
+    squared_l2: unsafe fn(&[f32], &[f32]) -> f32,
+    dot: unsafe fn(&[f32], &[f32]) -> f32,
+    l1: unsafe fn(&[f32], &[f32]) -> f32,
+}
+
+/// The widest instruction set the cpu supports, detected the first time any kernel is called
+static KERNELS: OnceLock<Kernels> = OnceLock::new();
+
+fn kernels() -> &'static Kernels {
+    KERNELS.get_or_init(detect)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn detect() -> Kernels {
+    if is_x86_feature_detected!("avx512f") {
+        AVX512
+    } else if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        AVX2
+    } else if is_x86_feature_detected!("sse2") {
+        SSE2
+    } else {
+        SCALAR
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn detect() -> Kernels {
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        NEON
+    } else {
+        SCALAR
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn detect() -> Kernels {
+    SCALAR
+}
+
+const SCALAR: Kernels = Kernels {
+    name: "scalar",
+    squared_l2: scalar::squared_l2,
+    dot: scalar::dot,
+    l1: scalar::l1,
+};
+
+#[cfg(target_arch = "x86_64")]
+const SSE2: Kernels = Kernels {
+    name: "sse2",
+    squared_l2: sse2::squared_l2,
+    dot: sse2::dot,
+    l1: sse2::l1,
+};
+
+#[cfg(target_arch = "x86_64")]
+const AVX2: Kernels = Kernels {
+    name: "avx2",
+    squared_l2: avx2::squared_l2,
+    dot: avx2::dot,
+    l1: avx2::l1,
+};
+
+#[cfg(target_arch = "x86_64")]
+const AVX512: Kernels = Kernels {
+    name: "avx512",
+    squared_l2: avx512::squared_l2,
+    dot: avx512::dot,
+    l1: avx512::l1,
+};
+
+#[cfg(target_arch = "aarch64")]
+const NEON: Kernels = Kernels {
+    name: "neon",
+    squared_l2: neon::squared_l2,
+    dot: neon::dot,
+    l1: neon::l1,
+};
+
+/// Name of the instruction set the kernels are using
+pub fn instruction_set() -> &'static str {
+    kernels().name
+}
+
+/// Sum of the squared differences between `l` and `r`
+pub fn squared_l2(l: &[f32], r: &[f32]) -> f32 {
+    assert_eq!(l.len(), r.len());
+    // SAFETY: the kernels were picked for this cpu and the lengths match
+    unsafe { (kernels().squared_l2)(l, r) }
+}
+
+/// Sum of the products of `l` and `r`
+#[expect(dead_code, reason = "search only compares with squared l2 so far")]
+pub fn dot(l: &[f32], r: &[f32]) -> f32 {
+    assert_eq!(l.len(), r.len());
+    // SAFETY: the kernels were picked for this cpu and the lengths match
+    unsafe { (kernels().dot)(l, r) }
+}
+
+/// Sum of the absolute differences between `l` and `r`
+#[expect(dead_code, reason = "search only compares with squared l2 so far")]
+pub fn l1(l: &[f32], r: &[f32]) -> f32 {
+    assert_eq!(l.len(), r.len());
+    // SAFETY: the kernels were picked for this cpu and the lengths match
+    unsafe { (kernels().l1)(l, r) }
+}
+
+mod scalar {
+    pub fn squared_l2(l: &[f32], r: &[f32]) -> f32 {
+        l.iter().zip(r).map(|(l, r)| (l - r).powi(2)).sum()
+    }
+
+    pub fn dot(l: &[f32], r: &[f32]) -> f32 {
+        l.iter().zip(r).map(|(l, r)| l * r).sum()
+    }
+
+    pub fn l1(l: &[f32], r: &[f32]) -> f32 {
+        l.iter().zip(r).map(|(l, r)| (l - r).abs()).sum()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use std::arch::x86_64::*;
+
+    const LANES: usize = 4;
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn sum(values: __m128) -> f32 {
+        let mut lanes = [0.0; LANES];
+        _mm_storeu_ps(lanes.as_mut_ptr(), values);
+        lanes.iter().sum()
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn squared_l2(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        let mut total = _mm_setzero_ps();
+
+        for index in (0..split).step_by(LANES) {
+            let difference = _mm_sub_ps(
+                _mm_loadu_ps(l.as_ptr().add(index)),
+                _mm_loadu_ps(r.as_ptr().add(index)),
+            );
+            total = _mm_add_ps(total, _mm_mul_ps(difference, difference));
+        }
+
+        sum(total) + super::scalar::squared_l2(&l[split..], &r[split..])
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn dot(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        let mut total = _mm_setzero_ps();
+
+        for index in (0..split).step_by(LANES) {
+            total = _mm_add_ps(
+                total,
+                _mm_mul_ps(
+                    _mm_loadu_ps(l.as_ptr().add(index)),
+                    _mm_loadu_ps(r.as_ptr().add(index)),
+                ),
+            );
+        }
+
+        sum(total) + super::scalar::dot(&l[split..], &r[split..])
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn l1(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        // clearing the sign bit is the absolute value
+        let sign = _mm_set1_ps(-0.0);
+        let mut total = _mm_setzero_ps();
+
+        for index in (0..split).step_by(LANES) {
+            let difference = _mm_sub_ps(
+                _mm_loadu_ps(l.as_ptr().add(index)),
+                _mm_loadu_ps(r.as_ptr().add(index)),
+            );
+            total = _mm_add_ps(total, _mm_andnot_ps(sign, difference));
+        }
+
+        sum(total) + super::scalar::l1(&l[split..], &r[split..])
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    const LANES: usize = 8;
+
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn sum(values: __m256) -> f32 {
+        let mut lanes = [0.0; LANES];
+        _mm256_storeu_ps(lanes.as_mut_ptr(), values);
+        lanes.iter().sum()
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn squared_l2(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        let mut total = _mm256_setzero_ps();
+
+        for index in (0..split).step_by(LANES) {
+            let difference = _mm256_sub_ps(
+                _mm256_loadu_ps(l.as_ptr().add(index)),
+                _mm256_loadu_ps(r.as_ptr().add(index)),
+            );
+            total = _mm256_fmadd_ps(difference, difference, total);
+        }
+
+        sum(total) + super::scalar::squared_l2(&l[split..], &r[split..])
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn dot(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        let mut total = _mm256_setzero_ps();
+
+        for index in (0..split).step_by(LANES) {
+            total = _mm256_fmadd_ps(
+                _mm256_loadu_ps(l.as_ptr().add(index)),
+                _mm256_loadu_ps(r.as_ptr().add(index)),
+                total,
+            );
+        }
+
+        sum(total) + super::scalar::dot(&l[split..], &r[split..])
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn l1(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        // clearing the sign bit is the absolute value
+        let sign = _mm256_set1_ps(-0.0);
+        let mut total = _mm256_setzero_ps();
+
+        for index in (0..split).step_by(LANES) {
+            let difference = _mm256_sub_ps(
+                _mm256_loadu_ps(l.as_ptr().add(index)),
+                _mm256_loadu_ps(r.as_ptr().add(index)),
+            );
+            total = _mm256_add_ps(total, _mm256_andnot_ps(sign, difference));
+        }
+
+        sum(total) + super::scalar::l1(&l[split..], &r[split..])
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx512 {
+    use std::arch::x86_64::*;
+
+    const LANES: usize = 16;
+
+    #[target_feature(enable = "avx512f")]
+    pub unsafe fn squared_l2(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        let mut total = _mm512_setzero_ps();
+
+        for index in (0..split).step_by(LANES) {
+            let difference = _mm512_sub_ps(
+                _mm512_loadu_ps(l.as_ptr().add(index)),
+                _mm512_loadu_ps(r.as_ptr().add(index)),
+            );
+            total = _mm512_fmadd_ps(difference, difference, total);
+        }
+
+        _mm512_reduce_add_ps(total) + super::scalar::squared_l2(&l[split..], &r[split..])
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub unsafe fn dot(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        let mut total = _mm512_setzero_ps();
+
+        for index in (0..split).step_by(LANES) {
+            total = _mm512_fmadd_ps(
+                _mm512_loadu_ps(l.as_ptr().add(index)),
+                _mm512_loadu_ps(r.as_ptr().add(index)),
+                total,
+            );
+        }
+
+        _mm512_reduce_add_ps(total) + super::scalar::dot(&l[split..], &r[split..])
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub unsafe fn l1(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        let mut total = _mm512_setzero_ps();
+
+        for index in (0..split).step_by(LANES) {
+            let difference = _mm512_sub_ps(
+                _mm512_loadu_ps(l.as_ptr().add(index)),
+                _mm512_loadu_ps(r.as_ptr().add(index)),
+            );
+            total = _mm512_add_ps(total, _mm512_abs_ps(difference));
+        }
+
+        _mm512_reduce_add_ps(total) + super::scalar::l1(&l[split..], &r[split..])
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    const LANES: usize = 4;
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn squared_l2(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        let mut total = vdupq_n_f32(0.0);
+
+        for index in (0..split).step_by(LANES) {
+            let difference = vsubq_f32(
+                vld1q_f32(l.as_ptr().add(index)),
+                vld1q_f32(r.as_ptr().add(index)),
+            );
+            total = vfmaq_f32(total, difference, difference);
+        }
+
+        vaddvq_f32(total) + super::scalar::squared_l2(&l[split..], &r[split..])
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn dot(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        let mut total = vdupq_n_f32(0.0);
+
+        for index in (0..split).step_by(LANES) {
+            total = vfmaq_f32(
+                total,
+                vld1q_f32(l.as_ptr().add(index)),
+                vld1q_f32(r.as_ptr().add(index)),
+            );
+        }
+
+        vaddvq_f32(total) + super::scalar::dot(&l[split..], &r[split..])
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn l1(l: &[f32], r: &[f32]) -> f32 {
+        let split = l.len() - l.len() % LANES;
+        let mut total = vdupq_n_f32(0.0);
+
+        for index in (0..split).step_by(LANES) {
+            total = vaddq_f32(
+                total,
+                vabdq_f32(
+                    vld1q_f32(l.as_ptr().add(index)),
+                    vld1q_f32(r.as_ptr().add(index)),
+                ),
+            );
+        }
+
+        vaddvq_f32(total) + super::scalar::l1(&l[split..], &r[split..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Around every lane width, so the remainder after the last full register is checked too
+    const LENGTHS: [usize; 18] = [
+        0, 1, 2, 3, 4, 5, 7, 8, 9, 13, 15, 16, 17, 31, 33, 100, 257, 1000,
+    ];
+
+    /// Every instruction set this cpu supports, besides scalar
+    fn supported() -> Vec<Kernels> {
+        #[allow(unused_mut, reason = "nothing is pushed on other architectures")]
+        let mut supported = Vec::new();
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("sse2") {
+                supported.push(SSE2);
+            }
+            if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+                supported.push(AVX2);
+            }
+            if is_x86_feature_detected!("avx512f") {
+                supported.push(AVX512);
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            supported.push(NEON);
+        }
+
+        supported
+    }
+
+    fn values(len: usize, phase: f32) -> Vec<f32> {
+        (0..len)
+            .map(|index| (index as f32 * 0.37 + phase).sin() * 3.0 - 0.5)
+            .collect()
+    }
+
+    fn assert_parity(
+        name: &str,
+        kernel: unsafe fn(&[f32], &[f32]) -> f32,
+        scalar: fn(&[f32], &[f32]) -> f32,
+    ) {
+        for len in LENGTHS {
+            let l = values(len, 0.0);
+            let r = values(len, 1.3);
+
+            // SAFETY: only kernels the cpu supports are tested, with equal lengths
+            let simd = unsafe { kernel(&l, &r) };
+            let expected = scalar(&l, &r);
+            // summing in a different order only loses precision relative to the size of the terms,
+            // and `(|l| + |r|)²` is at least as big as every kernel's terms for these values
+            let magnitude = l
+                .iter()
+                .zip(&r)
+                .map(|(l, r)| (l.abs() + r.abs()).powi(2))
+                .sum::<f32>();
+
+            assert!(
+                (simd - expected).abs() <= 1e-5 * magnitude.max(1.0),
+                "{name} with {len} values: {simd} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn squared_l2_matches_scalar() {
+        for kernels in supported() {
+            assert_parity(kernels.name, kernels.squared_l2, scalar::squared_l2);
+        }
+    }
+
+    #[test]
+    fn dot_matches_scalar() {
+        for kernels in supported() {
+            assert_parity(kernels.name, kernels.dot, scalar::dot);
+        }
+    }
+
+    #[test]
+    fn l1_matches_scalar() {
+        for kernels in supported() {
+            assert_parity(kernels.name, kernels.l1, scalar::l1);
+        }
+    }
+}
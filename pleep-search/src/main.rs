@@ -1,11 +1,10 @@
-use std::{
-    collections::{HashMap, VecDeque},
-    path::PathBuf,
-};
+use std::{collections::HashMap, path::PathBuf};
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use tracing::{debug, info, warn};
 
+mod kernels;
+
 const DEFAULT_MAX_ERROR: f32 = 10.0;
 const DEFAULT_NUM_RESULTS: usize = 10;
 const DEFAULT_EXTRA_OFFSETS: usize = 10;
@@ -13,6 +12,12 @@ const DEFAULT_SEGMENT_TRIM_SIZE: usize = 20;
 const DEFAULT_SEGMENT_TRIM_STEP: usize = 3;
 const DEFAULT_MIN_VECTORS: usize = 6;
 const DEFAULT_SPECTROGRAM_PADDING: usize = 3;
+const DEFAULT_ONSET_TOLERANCE: usize = 1;
+const DEFAULT_GRIFFIN_LIM_ITERATIONS: usize = 32;
+const DEFAULT_ONSET_WINDOW: usize = 3;
+const DEFAULT_ONSET_OFFSET: f64 = 0.05;
+const DEFAULT_ONSET_MULTIPLIER: f64 = 1.0;
+const DEFAULT_ONSET_MIN_GAP: usize = 2;
 
 fn main() {
     {
@@ -28,18 +33,95 @@ fn main() {
     }
 
     let options = Options::parse();
+
+    if let Some(path) = &options.list_tracks {
+        if let Err(error) = list_tracks(path) {
+            eprintln!("failed to list the tracks of {}: {error}", path.display());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let time_range = options
+        .time_range
+        .time_range()
+        .unwrap_or_else(|error| error.format(&mut Options::command()).exit());
     let start = std::time::Instant::now();
 
-    let mut reader = std::io::BufReader::new(std::fs::File::open(&options.lookup_file).unwrap());
-    let file = pleep_build::file::File::read_from(&mut reader).unwrap();
+    let (Some(lookup_file), Some(audio_file)) = (&options.lookup_file, &options.audio_file) else {
+        unreachable!("both files are required without --list-tracks");
+    };
+
+    let file = read_lookup_file(lookup_file).unwrap_or_else(|error| {
+        match error {
+            pleep_build::file::Error::InvalidMagic(_) => {
+                eprintln!("{} is not a pleep file", lookup_file.display())
+            }
+            pleep_build::file::Error::UnsupportedVersion { found, .. } => eprintln!(
+                "{} was built with format v{found}, rebuild it",
+                lookup_file.display()
+            ),
+            error => eprintln!("failed to read {}: {error}", lookup_file.display()),
+        }
+        std::process::exit(1);
+    });
     info!(build_settings=?file.build_settings, "read search file");
 
-    let audio: pleep_audio::Audio<f32> = pleep_audio::ConvertingAudioIterator::new(
-        pleep_audio::AudioSource::from_file_path(&options.audio_file)
-            .expect("failed to get audio source"),
+    let fingerprints = match options.mode {
+        Mode::Hashes => Some(file.fingerprints.as_ref().unwrap_or_else(|| {
+            clap::Error::raw(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "--mode hashes needs a fingerprint index, which {} doesn't have, rebuild it with --fingerprints",
+                    lookup_file.display()
+                ),
+            )
+            .format(&mut Options::command())
+            .exit()
+        })),
+        Mode::Vectors => None,
+    };
+
+    let channel_mode = options
+        .channel_mode
+        .map_or(file.build_settings.channel_mode, |channel_mode| {
+            channel_mode.channel_mode(options.channel)
+        });
+    if channel_mode != file.build_settings.channel_mode {
+        warn!(
+            ?channel_mode,
+            file_channel_mode = ?file.build_settings.channel_mode,
+            "channel mode differs from the one the file was built with"
+        );
+    }
+
+    let audio = load_audio(
+        audio_file,
+        &options.tracks.track_selection(),
+        time_range,
+        channel_mode,
     )
-    .expect("failed to load file")
-    .remaining_to_audio();
+    .unwrap_or_else(|error| {
+        eprintln!("failed to load {}: {error}", audio_file.display());
+        std::process::exit(1);
+    });
+
+    let hop_len = file.build_settings.spectrogram_settings().hop_len();
+    // every offset is sliced off the start of the audio, so there has to be more than a hop of it
+    let max_offset = audio.sample_rate * hop_len / file.build_settings.resample_rate as usize;
+    if audio.samples.len() <= max_offset {
+        eprintln!(
+            "no audio in the requested range of {}, only {} samples were decoded",
+            audio_file.display(),
+            audio.samples.len()
+        );
+        std::process::exit(1);
+    }
+
+    if let Some(index) = fingerprints {
+        search_hashes(&file, index, &audio, &options, start);
+        return;
+    }
 
     let threadpool = rayon::ThreadPoolBuilder::new().build().unwrap();
     let (send, recv) = crossbeam::channel::unbounded();
@@ -47,47 +129,82 @@ fn main() {
     let mut errors = vec![f32::INFINITY; file.segments.len()];
     let mut trimmed_segments = Vec::new();
 
+    // contiguous, so the kernels can compare a whole window at once
+    let height = file.build_settings.vector_length() as usize;
+    let flat_segments = file
+        .segments
+        .iter()
+        .map(|segment| segment.vectors.concat())
+        .collect::<Vec<_>>();
+
+    // found once for every segment, rather than again for every offset and trim
+    let segment_onsets = flat_segments
+        .iter()
+        .map(|segment| {
+            if !options.onset_anchors {
+                return Vec::new();
+            }
+
+            find_onsets(segment, height, &options)
+        })
+        .collect::<Vec<_>>();
+
     for remove_pre in (0..=options.segment_trim_size).step_by(options.segment_trim_step) {
-        let trimmed = file
-            .segments
+        let trimmed = flat_segments
             .iter()
-            .map(|segment| &segment.vectors[(remove_pre.min(segment.vectors.len()))..])
+            .zip(&segment_onsets)
+            .map(|(segment, onsets)| TrimmedSegment {
+                vectors: &segment[(remove_pre * height).min(segment.len())..],
+                onsets: onsets
+                    .iter()
+                    .filter_map(|onset| onset.checked_sub(remove_pre))
+                    .collect(),
+            })
             .collect::<Vec<_>>();
 
         trimmed_segments.push(trimmed);
     }
 
-    threadpool.scope(|s| {
-        for trimmed in &trimmed_segments {
-            let mut slices = Vec::new();
-            for index in 0..=options.extra_offsets {
-                let offset = (index * audio.sample_rate * file.build_settings.fft_size as usize
-                    / file.build_settings.resample_rate as usize)
-                    / options.extra_offsets;
-                slices.push((offset, &audio.samples[offset..]));
-            }
+    let generator = file.build_settings.generator();
+    debug!(
+        instruction_set = kernels::instruction_set(),
+        "comparing vectors"
+    );
 
-            for (offset, slice) in slices {
-                let build_settings = &file.build_settings;
-                let options = &options;
-                let send = send.clone();
-
-                s.spawn(move |_s| {
-                    debug!(offset, "starting offset");
-
-                    let offset_errors = get_error(
-                        slice,
-                        audio.sample_rate,
-                        build_settings,
-                        options,
-                        options.min_vectors,
-                        trimmed,
-                        options.spectrogram_padding,
-                    );
-
-                    send.send(offset_errors).unwrap();
-                });
-            }
+    threadpool.scope(|s| {
+        for index in 0..=options.extra_offsets {
+            let offset = (index * audio.sample_rate * hop_len
+                / file.build_settings.resample_rate as usize)
+                / options.extra_offsets;
+            let slice = &audio.samples[offset..];
+            let build_settings = &file.build_settings;
+            let options = &options;
+            let generator = &generator;
+            let trimmed_segments = &trimmed_segments;
+            let send = send.clone();
+
+            s.spawn(move |s| {
+                debug!(offset, "starting offset");
+
+                // the audio's vectors don't depend on the trim, so they're made once and shared by every trim
+                let query = std::sync::Arc::new(Query::new(
+                    slice,
+                    audio.sample_rate,
+                    build_settings,
+                    options,
+                    generator,
+                    options.spectrogram_padding,
+                ));
+
+                for trimmed in trimmed_segments {
+                    let query = query.clone();
+                    let send = send.clone();
+
+                    s.spawn(move |_s| {
+                        send.send(get_error(&query, options, trimmed)).unwrap();
+                    });
+                }
+            });
         }
     });
     drop(send);
@@ -116,6 +233,15 @@ fn main() {
         }
     }
 
+    if options.debug_audio {
+        if !best.is_empty() {
+            let best_section = &file.segments[best[0].0];
+            save_audio("best.wav", &file.build_settings, &best_section.vectors);
+        } else {
+            warn!("no best segment, not creating best.wav");
+        }
+    }
+
     let top_n = best.into_iter().take(options.n_results).collect::<Vec<_>>();
 
     let max_observed_mse = top_n
@@ -155,6 +281,68 @@ fn main() {
     }
 }
 
+fn read_lookup_file(path: &PathBuf) -> Result<pleep_build::file::File, pleep_build::file::Error> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    pleep_build::file::File::read_from(&mut reader)
+}
+
+fn load_audio(
+    path: &PathBuf,
+    track: &pleep_audio::TrackSelection,
+    time_range: pleep_audio::TimeRange,
+    channel_mode: pleep_audio::ChannelMode,
+) -> Result<pleep_audio::Audio<f32>, pleep_build::cli::LoadError> {
+    let mut audio = pleep_audio::ConvertingAudioIterator::new_with_track(
+        pleep_audio::AudioSource::from_file_path(path)?,
+        track,
+    )?
+    .with_time_range(time_range)?
+    .with_channel_mode(channel_mode);
+
+    let sample_rate = audio.sample_rate() as usize;
+    let samples = audio.by_ref().collect::<Vec<_>>();
+
+    let health = audio.health();
+    if !health.is_clean() {
+        warn!(
+            packets_skipped = health.packets_skipped,
+            samples_lost = health.samples_lost,
+            resets = health.resets,
+            "audio did not decode cleanly"
+        );
+    }
+    // searching with only the start of the audio would look like a confident match for the wrong thing
+    if let Some(error) = audio.into_error() {
+        return Err(error.into());
+    }
+
+    Ok(pleep_audio::Audio {
+        samples,
+        sample_rate,
+    })
+}
+
+fn list_tracks(path: &PathBuf) -> Result<(), pleep_build::cli::LoadError> {
+    let tracks = pleep_audio::AudioSource::from_file_path(path)?.tracks()?;
+
+    for (index, track) in tracks.iter().enumerate() {
+        println!(
+            "{index}: id {}, codec {}, {} channels, {} hz, language {}",
+            track.id,
+            track.codec.unwrap_or("unsupported"),
+            track
+                .channels
+                .map_or("unknown".to_string(), |channels| channels.to_string()),
+            track
+                .sample_rate
+                .map_or("unknown".to_string(), |sample_rate| sample_rate.to_string()),
+            track.language.as_deref().unwrap_or("unknown"),
+        );
+    }
+
+    Ok(())
+}
+
 fn save_spectrogram(
     name: &str,
     vectors: &[Vec<f32>],
@@ -187,16 +375,68 @@ fn save_spectrogram(
     canvas
 }
 
-fn distance_sq(l1: &[f32], l2: &[f32]) -> f32 {
-    l1.iter().zip(l2).map(|(l, r)| (l - r).powi(2)).sum()
+fn save_audio(name: &str, build_settings: &pleep_build::file::BuildSettings, vectors: &[Vec<f32>]) {
+    let pleep_build::features::FeatureKind::LogSpectrogram = build_settings.feature else {
+        warn!(feature=?build_settings.feature, "only log spectrograms can be turned back into audio, not creating {name}");
+        return;
+    };
+
+    let samples = pleep_build::reconstruct_log_spectrogram(
+        vectors,
+        &build_settings.spectrogram_settings(),
+        &build_settings.log_spectrogram_settings(),
+        DEFAULT_GRIFFIN_LIM_ITERATIONS,
+    );
+
+    let mut writer = hound::WavWriter::create(
+        name,
+        hound::WavSpec {
+            channels: 1,
+            sample_rate: build_settings.resample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        },
+    )
+    .expect("failed to create debug audio file");
+
+    // the reconstruction is missing everything above the cutoff and between rows, so it can be much quieter
+    let peak = samples
+        .iter()
+        .fold(0f32, |peak, sample| peak.max(sample.abs()));
+    for sample in samples {
+        writer
+            .write_sample(if peak > 0.0 { sample / peak } else { sample })
+            .expect("failed to write debug audio");
+    }
+
+    writer.finalize().expect("failed to save debug audio file");
 }
 
 #[derive(Debug, clap::Parser, Clone)]
 struct Options {
     /// File that contains all of the spectrograms
-    lookup_file: PathBuf,
+    #[arg(required_unless_present = "list_tracks")]
+    lookup_file: Option<PathBuf>,
     /// File that audio should be read from
-    audio_file: PathBuf,
+    #[arg(required_unless_present = "list_tracks")]
+    audio_file: Option<PathBuf>,
+    /// How the audio is compared against the file
+    #[arg(long, value_enum, default_value_t = Mode::Vectors)]
+    mode: Mode,
+    /// Print the tracks in this audio file instead of searching
+    #[arg(long, value_name = "AUDIO_FILE", conflicts_with_all = ["lookup_file", "audio_file"])]
+    list_tracks: Option<PathBuf>,
+    #[command(flatten)]
+    tracks: pleep_build::cli::TrackOptions,
+    #[command(flatten)]
+    time_range: pleep_build::cli::TimeRangeOptions,
+    /// How the channels of multi-channel audio are turned into one, defaults to the mode the file was
+    /// built with
+    #[arg(long, value_enum)]
+    channel_mode: Option<pleep_build::cli::ChannelMode>,
+    /// Index of the channel used by the channel mode, starting from zero
+    #[arg(long, default_value_t = 0)]
+    channel: usize,
     /// Maximum mse to consider windows at
     #[arg(long, default_value_t = DEFAULT_MAX_ERROR)]
     max_error: f32,
@@ -209,6 +449,9 @@ struct Options {
     /// Generate some debug spectrograms for the fun of it
     #[arg(long, action = clap::ArgAction::SetTrue)]
     debug_images: bool,
+    /// Reconstruct the best matching segment as audio, saving it to `best.wav`
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    debug_audio: bool,
     /// The number of different offsets to try when creating shifted spectrograms
     #[arg(long, default_value_t = DEFAULT_EXTRA_OFFSETS)]
     extra_offsets: usize,
@@ -224,11 +467,64 @@ struct Options {
     /// Padding to apply to spectrograms
     #[arg(long, default_value_t = DEFAULT_SPECTROGRAM_PADDING)]
     spectrogram_padding: usize,
+    /// Also try every transposition of the query when the file stores chroma vectors
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    transpose: bool,
+    /// Only try alignments that line up an onset in the audio with an onset in the segment,
+    /// instead of every possible alignment
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    onset_anchors: bool,
+    /// Number of extra vectors either side of each onset alignment to also try
+    #[arg(long, default_value_t = DEFAULT_ONSET_TOLERANCE)]
+    onset_tolerance: usize,
+    /// How the novelty that onsets are picked from is found. It's computed from the vectors stored in the
+    /// file, so it's only as meaningful for mfccs, chroma or decibels as it is for magnitudes
+    #[arg(long, value_enum, default_value_t = OnsetFunction::SpectralFlux)]
+    onset_function: OnsetFunction,
+    /// Number of vectors either side used for the moving median, and that an onset must be the maximum over
+    #[arg(long, default_value_t = DEFAULT_ONSET_WINDOW)]
+    onset_window: usize,
+    /// Added to the moving median to make the threshold, relative to the largest novelty
+    #[arg(long, default_value_t = DEFAULT_ONSET_OFFSET)]
+    onset_offset: f64,
+    /// Multiplies the moving median when making the threshold
+    #[arg(long, default_value_t = DEFAULT_ONSET_MULTIPLIER)]
+    onset_multiplier: f64,
+    /// Smallest number of vectors between consecutive onsets
+    #[arg(long, default_value_t = DEFAULT_ONSET_MIN_GAP)]
+    onset_min_gap: usize,
+}
+
+impl Options {
+    fn onset_threshold(&self) -> pleep::onset::ThresholdSettings {
+        pleep::onset::ThresholdSettings {
+            window: self.onset_window,
+            offset: self.onset_offset,
+            multiplier: self.onset_multiplier,
+            min_gap: self.onset_min_gap,
+        }
+    }
+}
+
+/// Novelty functions that only need each vector's values, the complex domain needs phases the file doesn't keep
+#[derive(Debug, clap::ValueEnum, Clone, Copy)]
+enum OnsetFunction {
+    SpectralFlux,
+    HighFrequencyContent,
+}
+
+#[derive(Debug, clap::ValueEnum, Clone, Copy)]
+enum Mode {
+    /// Slide every segment's vectors over the audio's vectors, looking for the lowest error
+    Vectors,
+    /// Look up the audio's landmark hashes and vote on consistent time offsets,
+    /// requires the file to be built with `--fingerprints`
+    Hashes,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
-struct CommandOutput {
-    matches: Vec<Match>,
+struct CommandOutput<M> {
+    matches: Vec<M>,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -238,16 +534,22 @@ struct Match {
     confidence: f32,
 }
 
-fn get_error(
+#[derive(Debug, Clone, serde::Serialize)]
+struct HashMatch {
+    title: String,
+    /// Number of landmarks that agree on the offset
+    votes: u32,
+    /// Seconds into the segment that the audio starts at
+    offset: f32,
+    confidence: f32,
+}
+
+fn resample(
     samples: &[f32],
     sample_rate: usize,
     build_settings: &pleep_build::file::BuildSettings,
-    options: &Options,
-    skip_less_than: usize,
-    segments: &[&[Vec<f32>]],
-    spectrogram_padding: usize,
-) -> HashMap<usize, f32> {
-    let resample = pleep_audio::ResamplingChunksIterator::new(
+) -> Vec<f32> {
+    pleep_audio::ResamplingChunksIterator::new(
         samples.iter().copied(),
         sample_rate,
         pleep_build::cli::ResampleSettings {
@@ -257,44 +559,179 @@ fn get_error(
         }
         .into(),
     )
-    .unwrap();
+    .unwrap()
+    .flatten()
+    .collect()
+}
 
-    let mut spectrogram = pleep_build::generate_log_spectrogram(
-        resample.flatten().collect::<Vec<_>>(),
-        &pleep_build::cli::SpectrogramSettings {
-            fft_overlap: build_settings.fft_overlap as usize,
-            fft_size: build_settings.fft_size as usize,
+fn search_hashes(
+    file: &pleep_build::file::File,
+    index: &pleep_build::fingerprint::FingerprintIndex,
+    audio: &pleep_audio::Audio<f32>,
+    options: &Options,
+    start: std::time::Instant,
+) {
+    let resampled = resample(&audio.samples, audio.sample_rate, &file.build_settings);
+
+    let mut processor = pleep_build::features::FeatureProcessor::new(
+        &file.build_settings.spectrogram_settings(),
+        &file.build_settings.resolutions(),
+        &file.build_settings.feature_settings(),
+        &file.build_settings.generator(),
+    );
+    let mut vectors = Vec::new();
+    processor.process_into_vec(&resampled, &mut vectors);
+    let vectors = vectors.chunks_exact(processor.height()).collect::<Vec<_>>();
+
+    let landmarks = index.landmarks_for(&vectors);
+    debug!(n_landmarks = landmarks.len(), "found landmarks in audio");
+
+    // a match has many landmarks sharing the same difference between segment and audio frames
+    let mut votes: HashMap<(u32, i64), u32> = HashMap::new();
+    for landmark in &landmarks {
+        for posting in index.get(landmark.hash) {
+            let offset = posting.frame as i64 - landmark.frame as i64;
+            *votes.entry((posting.segment, offset)).or_default() += 1;
         }
-        .into(),
-        &pleep_build::LogSpectrogramSettings {
-            height: build_settings.spectrogram_height as usize,
-            frequency_cutoff: build_settings.spectrogram_max_frequency as usize,
-            input_sample_rate: build_settings.resample_rate as usize,
-            base: build_settings.log_base,
-        },
-    )
-    .collect::<VecDeque<_>>();
+    }
+
+    let mut best_offsets: HashMap<u32, (u32, i64)> = HashMap::new();
+    for ((segment, offset), count) in votes {
+        let best = best_offsets.entry(segment).or_insert((count, offset));
+        // the earliest offset wins a tie, rather than whichever the map happened to visit first
+        if count > best.0 || (count == best.0 && offset < best.1) {
+            *best = (count, offset);
+        }
+    }
+
+    let mut best = best_offsets.into_iter().collect::<Vec<_>>();
+    best.sort_by(|(l_segment, (l_votes, _)), (r_segment, (r_votes, _))| {
+        r_votes.cmp(l_votes).then(l_segment.cmp(r_segment))
+    });
+
+    let hop_len = file.build_settings.spectrogram_settings().hop_len();
+    let matches = best
+        .into_iter()
+        .take(options.n_results)
+        .map(|(segment, (votes, offset))| HashMatch {
+            title: file.segments[segment as usize].title.clone(),
+            votes,
+            offset: (offset * hop_len as i64) as f32 / file.build_settings.resample_rate as f32,
+            confidence: votes as f32 / landmarks.len().max(1) as f32,
+        })
+        .collect::<Vec<_>>();
 
-    debug!(len = spectrogram.len(), "created spectrogram");
+    let elapsed_time = start.elapsed();
 
-    // TODO: make this only happen on one iteration
-    // if options.debug_images {
-    //     save_spectrogram("input.png", &spectrogram);
-    // }
+    for (index, hash_match) in matches.iter().enumerate() {
+        info!(
+            votes = hash_match.votes,
+            offset = hash_match.offset,
+            confidence = hash_match.confidence,
+            "{index: >4}: {}",
+            hash_match.title
+        );
+    }
+    debug!(?elapsed_time, "done");
 
-    let empty_vec = vec![0.0; spectrogram[0].len()];
-    for _ in 0..spectrogram_padding {
-        spectrogram.push_front(empty_vec.clone());
-        spectrogram.push_back(empty_vec.clone());
+    if options.json {
+        print!(
+            "{}",
+            serde_json::to_string(&CommandOutput { matches }).unwrap()
+        );
     }
-    let spectrogram = spectrogram.make_contiguous();
+}
+
+/// A segment's vectors with some removed from the beginning
+struct TrimmedSegment<'a> {
+    /// Flat `[vectors × height]` buffer
+    vectors: &'a [f32],
+    /// Indices of the onsets in `vectors`
+    onsets: Vec<usize>,
+}
+
+/// The audio's vectors starting from one offset
+struct Query {
+    /// Flat `[vectors × height]` buffers, the first is the audio's and the rest are its transpositions
+    spectrograms: Vec<Vec<f32>>,
+    /// Number of vectors in each spectrogram
+    len: usize,
+    height: usize,
+    /// Indices of the onsets in the spectrograms, when they're used to anchor alignments
+    onsets: Option<Vec<usize>>,
+}
+
+impl Query {
+    fn new(
+        samples: &[f32],
+        sample_rate: usize,
+        build_settings: &pleep_build::file::BuildSettings,
+        options: &Options,
+        generator: &pleep::spectrogram::Generator<f32>,
+        spectrogram_padding: usize,
+    ) -> Self {
+        let resampled = resample(samples, sample_rate, build_settings);
+
+        let mut processor = pleep_build::features::FeatureProcessor::new(
+            &build_settings.spectrogram_settings(),
+            &build_settings.resolutions(),
+            &build_settings.feature_settings(),
+            generator,
+        );
+        let height = processor.height();
+        let frames = processor.frames(resampled.len());
+
+        debug!(len = frames, "created spectrogram");
+
+        // TODO: make this only happen on one iteration
+        // if options.debug_images {
+        //     save_spectrogram("input.png", &spectrogram);
+        // }
+
+        // the padding columns on either side are silence, which is only zeros on a linear scale
+        let mut spectrogram = processor.silence().repeat(frames + 2 * spectrogram_padding);
+        processor.process(
+            &resampled,
+            &mut spectrogram[spectrogram_padding * height..(spectrogram_padding + frames) * height],
+        );
+
+        let onsets = options
+            .onset_anchors
+            .then(|| find_onsets(&spectrogram, height, options));
+
+        let transpositions = match build_settings.feature {
+            pleep_build::features::FeatureKind::Chroma { .. } if options.transpose => {
+                pleep::chroma::N_CHROMA
+            }
+            _ => 1,
+        };
+        let mut spectrograms = vec![spectrogram];
+        for shift in 1..transpositions {
+            let mut shifted = spectrograms[0].clone();
+            pleep::chroma::transpose(&mut shifted, shift);
+            spectrograms.push(shifted);
+        }
+
+        Self {
+            spectrograms,
+            len: frames + 2 * spectrogram_padding,
+            height,
+            onsets,
+        }
+    }
+}
+
+fn get_error(query: &Query, options: &Options, segments: &[TrimmedSegment]) -> HashMap<usize, f32> {
+    let height = query.height;
+    let spectrogram_len = query.len;
 
     let before_len = segments.len();
     let filtered_segments = segments
         .iter()
+        .map(|segment| (segment, segment.vectors.len() / height))
         .enumerate()
-        .filter(|(_, segment)| segment.len() <= spectrogram.len())
-        .filter(|(_, segment)| segment.len() >= skip_less_than)
+        .filter(|(_, (_, segment_len))| *segment_len <= spectrogram_len)
+        .filter(|(_, (_, segment_len))| *segment_len >= options.min_vectors)
         .collect::<Vec<_>>();
     debug!(
         before_len,
@@ -304,16 +741,33 @@ fn get_error(
 
     let mut scores = HashMap::new();
 
-    for (segment_index, segment) in &filtered_segments {
+    for (segment_index, (segment, segment_len)) in &filtered_segments {
+        let max_start = spectrogram_len - segment_len;
+        let starts = match &query.onsets {
+            Some(query_onsets) => onset_aligned_starts(
+                query_onsets,
+                &segment.onsets,
+                max_start,
+                options.onset_tolerance,
+            ),
+            None => Vec::new(),
+        };
+        // without any onsets to line up, every alignment has to be tried
+        let starts = if starts.is_empty() {
+            (0..=max_start).collect()
+        } else {
+            starts
+        };
+
         let mut min_error = f32::INFINITY;
-        for spectrogram_window in spectrogram.windows(segment.len()) {
-            let error = spectrogram_window
-                .iter()
-                .zip(segment.iter())
-                .map(|(spect_vect, segment_vect)| distance_sq(spect_vect, segment_vect))
-                .sum::<f32>()
-                / spectrogram_window.len() as f32;
-            min_error = min_error.min(error);
+        for spectrogram in &query.spectrograms {
+            for &start in &starts {
+                let spectrogram_window =
+                    &spectrogram[start * height..(start + segment_len) * height];
+                let error =
+                    kernels::squared_l2(spectrogram_window, segment.vectors) / *segment_len as f32;
+                min_error = min_error.min(error);
+            }
         }
 
         if min_error > options.max_error {
@@ -325,3 +779,88 @@ fn get_error(
 
     scores
 }
+
+/// Indices of the onsets in a flat `[vectors × height]` buffer
+fn find_onsets(vectors: &[f32], height: usize, options: &Options) -> Vec<usize> {
+    let columns = vectors.chunks_exact(height).collect::<Vec<_>>();
+    let novelty = match options.onset_function {
+        OnsetFunction::SpectralFlux => pleep::onset::spectral_flux(&columns),
+        OnsetFunction::HighFrequencyContent => pleep::onset::high_frequency_content(&columns),
+    };
+
+    pleep::onset::pick_onsets(&novelty, &options.onset_threshold())
+}
+
+/// Alignments of a segment that put one of `segment_onsets` within `tolerance` vectors of one of `query_onsets`
+fn onset_aligned_starts(
+    query_onsets: &[usize],
+    segment_onsets: &[usize],
+    max_start: usize,
+    tolerance: usize,
+) -> Vec<usize> {
+    let mut starts = query_onsets
+        .iter()
+        .flat_map(|query_onset| {
+            segment_onsets.iter().flat_map(move |segment_onset| {
+                let start = *query_onset as isize - *segment_onset as isize;
+                (start - tolerance as isize)..=(start + tolerance as isize)
+            })
+        })
+        .filter(|start| (0..=max_start as isize).contains(start))
+        .map(|start| start as usize)
+        .collect::<Vec<_>>();
+
+    starts.sort_unstable();
+    starts.dedup();
+    starts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEIGHT: usize = 4;
+
+    /// Quiet vectors that get loud at `step`
+    fn stepped(len: usize, step: usize) -> Vec<f32> {
+        (0..len)
+            .flat_map(|index| [if index < step { 0.1 } else { 1.0 }; HEIGHT])
+            .collect()
+    }
+
+    #[test]
+    fn onset_anchors_narrow_the_alignments_to_matching_onsets() {
+        let options = Options::parse_from(["pleep-search", "file", "audio", "--onset-anchors"]);
+
+        let query_onsets = find_onsets(&stepped(40, 25), HEIGHT, &options);
+        let segment_onsets = find_onsets(&stepped(20, 8), HEIGHT, &options);
+        assert_eq!(query_onsets, vec![25]);
+        assert_eq!(segment_onsets, vec![8]);
+
+        // every one of the 21 alignments would be tried without anchors
+        let max_start = 40 - 20;
+        assert_eq!(
+            onset_aligned_starts(&query_onsets, &segment_onsets, max_start, 1),
+            vec![16, 17, 18]
+        );
+        assert_eq!(
+            onset_aligned_starts(&query_onsets, &segment_onsets, max_start, 0),
+            vec![17]
+        );
+    }
+
+    #[test]
+    fn onset_threshold_comes_from_the_options() {
+        let options = Options::parse_from([
+            "pleep-search",
+            "file",
+            "audio",
+            "--onset-anchors",
+            "--onset-offset",
+            "2",
+        ]);
+
+        // no normalised novelty can clear an offset above 1
+        assert!(find_onsets(&stepped(40, 25), HEIGHT, &options).is_empty());
+    }
+}
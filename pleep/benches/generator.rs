@@ -0,0 +1,136 @@
+//! Compares sharing one [`Generator`] between rayon tasks that each build a spectrogram processor, against
+//! the generator from before plans and windows could be prepared, which locked a shared planner and
+//! window cache on every lookup.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use pleep::{
+    spectrogram::{FrameProcessor, Generator, Settings, SpectrogramProcessor},
+    window::WindowFunction,
+};
+use rayon::prelude::*;
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+const TASKS: usize = 256;
+
+type WindowCache = HashMap<(WindowFunction, usize), Arc<Vec<f32>>>;
+
+/// The generator from before this redesign
+#[derive(Default)]
+struct LockedGenerator {
+    fft_planner: Mutex<RealFftPlanner<f32>>,
+    windows: RwLock<WindowCache>,
+}
+
+impl LockedGenerator {
+    fn get_forward_fft(&self, len: usize) -> Arc<dyn RealToComplex<f32>> {
+        self.fft_planner.lock().unwrap().plan_fft_forward(len)
+    }
+
+    fn get_window(&self, window_function: WindowFunction, size: usize) -> Arc<Vec<f32>> {
+        if let Some(window) = self.windows.read().unwrap().get(&(window_function, size)) {
+            return window.clone();
+        }
+
+        let window = Arc::new(window_function.generate(size));
+        self.windows
+            .write()
+            .unwrap()
+            .insert((window_function, size), window.clone());
+        window
+    }
+
+    /// The same work as [`SpectrogramProcessor::new`] followed by [`FrameProcessor::process_frame`]
+    fn process_frame(&self, settings: &Settings, frame: &[f32], out: &mut [f32]) {
+        let fft = self.get_forward_fft(settings.fft_len);
+        let window = self.get_window(settings.window_function, settings.window_len);
+
+        let mut input = fft.make_input_vec();
+        let mut output = fft.make_output_vec();
+        let mut scratch = fft.make_scratch_vec();
+        for ((input, sample), weight) in input.iter_mut().zip(frame).zip(window.iter()) {
+            *input = sample * weight;
+        }
+        fft.process_with_scratch(&mut input, &mut output, &mut scratch)
+            .unwrap();
+
+        let normalisation = (window.len() as f32).sqrt();
+        for (value, bin) in out.iter_mut().zip(&output) {
+            *value = Complex::norm(*bin) / normalisation;
+        }
+    }
+}
+
+fn settings(fft_len: usize) -> Settings {
+    Settings {
+        fft_len,
+        window_len: fft_len,
+        fft_overlap: fft_len / 2,
+        window_function: WindowFunction::Hann,
+        center: false,
+    }
+}
+
+fn fan_out(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("generator fan-out");
+
+    for fft_len in [1024, 8192] {
+        let settings = settings(fft_len);
+        let frame = (0..fft_len)
+            .map(|n| (n as f32 * 0.01).sin())
+            .collect::<Vec<_>>();
+
+        let locked = LockedGenerator::default();
+        group.bench_with_input(
+            BenchmarkId::new("locked", fft_len),
+            &settings,
+            |bench, settings| {
+                bench.iter(|| {
+                    (0..TASKS).into_par_iter().for_each(|_| {
+                        let mut column = vec![0.0; fft_len / 2];
+                        locked.process_frame(settings, &frame, &mut column);
+                    })
+                })
+            },
+        );
+
+        let lazy = Generator::new();
+        group.bench_with_input(
+            BenchmarkId::new("once map", fft_len),
+            &settings,
+            |bench, settings| {
+                bench.iter(|| {
+                    (0..TASKS).into_par_iter().for_each(|_| {
+                        let mut processor = SpectrogramProcessor::new(settings.clone(), &lazy);
+                        let mut column = vec![0.0; processor.bins()];
+                        processor.process_frame(&frame, &mut column);
+                    })
+                })
+            },
+        );
+
+        let prepared = Generator::new().with_settings(&settings);
+        group.bench_with_input(
+            BenchmarkId::new("prepared", fft_len),
+            &settings,
+            |bench, settings| {
+                bench.iter(|| {
+                    (0..TASKS).into_par_iter().for_each(|_| {
+                        let mut processor = SpectrogramProcessor::new(settings.clone(), &prepared);
+                        let mut column = vec![0.0; processor.bins()];
+                        processor.process_frame(&frame, &mut column);
+                    })
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, fan_out);
+criterion_main!(benches);
@@ -0,0 +1,113 @@
+use alloc::{vec, vec::Vec};
+
+#[cfg(not(feature = "std"))]
+#[allow(
+    unused_imports,
+    reason = "unused when std is linked anyway, as it is for tests"
+)]
+use num_traits::Float as _;
+
+use crate::spectrogram::{get_frequency_for_bin, Float, SpectrogramIterator};
+
+/// Number of pitch classes in each chroma vector, starting from C
+pub const N_CHROMA: usize = 12;
+
+/// Midi note number of the tuning reference (A4)
+const TUNING_NOTE: f64 = 69.0;
+
+/// Moves every pitch class of flat chroma vectors down by `semitones`, so audio shifted up by that many
+/// semitones lines up with the original.
+///
+/// Each run of [`N_CHROMA`] values is rotated on its own, so vectors with several chroma vectors stacked
+/// in them are transposed correctly.
+pub fn transpose<S: Copy>(vectors: &mut [S], semitones: usize) {
+    assert_eq!(vectors.len() % N_CHROMA, 0);
+
+    for chroma in vectors.chunks_exact_mut(N_CHROMA) {
+        chroma.rotate_left(semitones % N_CHROMA);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Frequency of A4, usually 440hz
+    pub tuning_frequency: f64,
+    pub min_frequency: f64,
+    pub max_frequency: f64,
+}
+
+/// Folds linear spectrogram columns into the 12 pitch classes
+#[derive(Debug, Clone)]
+pub struct ChromaFilterbank {
+    /// Each bin that falls within the frequency range, along with its pitch class
+    bins: Vec<(usize, usize)>,
+}
+
+impl ChromaFilterbank {
+    /// Creates a filterbank for columns produced from ffts of length `fft_len` at `sample_rate`
+    pub fn new(settings: &Settings, sample_rate: usize, fft_len: usize) -> Self {
+        let bins = (1..fft_len / 2)
+            .filter_map(|bin| {
+                let frequency = get_frequency_for_bin(bin, sample_rate, fft_len);
+
+                if frequency < settings.min_frequency || frequency > settings.max_frequency {
+                    return None;
+                }
+
+                let note = TUNING_NOTE + 12.0 * (frequency / settings.tuning_frequency).log2();
+                let class = (note.round() as i64).rem_euclid(N_CHROMA as i64) as usize;
+
+                Some((bin, class))
+            })
+            .collect();
+
+        Self { bins }
+    }
+
+    /// Sums the energy of each pitch class in `column` into `out`, scaled so the loudest class is 1
+    pub fn apply_into<S: Float>(&self, column: &[S], out: &mut [S]) {
+        assert_eq!(out.len(), N_CHROMA);
+
+        out.fill(S::zero());
+
+        for (bin, class) in &self.bins {
+            let magnitude = column[*bin];
+            out[*class] = out[*class] + magnitude * magnitude;
+        }
+
+        let max = out.iter().copied().fold(S::zero(), S::max);
+        if max > S::zero() {
+            for value in out.iter_mut() {
+                *value = *value / max;
+            }
+        }
+    }
+
+    pub fn apply<S: Float>(&self, column: &[S]) -> Vec<S> {
+        let mut out = vec![S::zero(); N_CHROMA];
+        self.apply_into(column, &mut out);
+        out
+    }
+}
+
+pub struct ChromaIterator<S: Float, T: Iterator<Item = S>> {
+    inner: SpectrogramIterator<S, T>,
+    filterbank: ChromaFilterbank,
+}
+
+impl<S: Float, T: Iterator<Item = S>> ChromaIterator<S, T> {
+    pub fn new(spectrogram: SpectrogramIterator<S, T>, filterbank: ChromaFilterbank) -> Self {
+        Self {
+            inner: spectrogram,
+            filterbank,
+        }
+    }
+}
+
+impl<S: Float, T: Iterator<Item = S>> Iterator for ChromaIterator<S, T> {
+    type Item = Vec<S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|col| self.filterbank.apply(&col))
+    }
+}
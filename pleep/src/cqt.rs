@@ -0,0 +1,251 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use num_complex::Complex;
+#[cfg(not(feature = "std"))]
+#[allow(
+    unused_imports,
+    reason = "unused when std is linked anyway, as it is for tests"
+)]
+use num_traits::Float as _;
+
+use crate::{
+    fft::RealToComplex,
+    spectrogram::{Float, FrameIterator, FrameProcessor, Generator},
+    window::WindowFunction,
+};
+
+/// Spectral kernel values smaller than this fraction of a kernel's peak are dropped
+const SPARSITY_THRESHOLD: f64 = 0.005;
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub sample_rate: usize,
+    /// Centre frequency of the lowest bin
+    pub min_frequency: f64,
+    pub bins_per_octave: usize,
+    pub n_octaves: usize,
+    /// Number of samples between the starts of consecutive frames
+    pub hop_len: usize,
+    pub window_function: WindowFunction,
+}
+
+impl Settings {
+    pub fn bins(&self) -> usize {
+        self.bins_per_octave * self.n_octaves
+    }
+
+    /// Ratio of each bin's centre frequency to its bandwidth
+    pub fn q(&self) -> f64 {
+        1.0 / (2f64.powf(1.0 / self.bins_per_octave as f64) - 1.0)
+    }
+
+    pub fn get_frequency_for_bin(&self, bin: usize) -> f64 {
+        self.min_frequency * 2f64.powf(bin as f64 / self.bins_per_octave as f64)
+    }
+}
+
+/// Computes constant-Q columns using sparse spectral kernels (Brown & Puckette),
+/// so each frame costs one fft plus a short dot product per bin.
+pub struct ConstantQProcessor<S: Float> {
+    settings: Settings,
+    frame_len: usize,
+    /// The first fft bin each kernel covers, and its conjugated weights from that bin onwards
+    kernels: Vec<(usize, Vec<Complex<S>>)>,
+    fft: Arc<dyn RealToComplex<S>>,
+    fft_input: Vec<S>,
+    fft_output: Vec<Complex<S>>,
+    fft_scratch: Vec<Complex<S>>,
+}
+
+impl<S: Float> ConstantQProcessor<S> {
+    pub fn new(settings: Settings, generator: &Generator<S>) -> Self {
+        assert!(settings.hop_len > 0, "hop length must be positive");
+        assert!(
+            settings.min_frequency > 0.0,
+            "minimum frequency ({}hz) must be positive",
+            settings.min_frequency
+        );
+
+        let q = settings.q();
+        let max_frequency = settings.get_frequency_for_bin(settings.bins().saturating_sub(1));
+        assert!(
+            max_frequency < settings.sample_rate as f64 / 2.0,
+            "highest constant-q bin ({max_frequency}hz) must be below the nyquist frequency"
+        );
+
+        let kernel_len =
+            |frequency: f64| (q * settings.sample_rate as f64 / frequency).ceil() as usize;
+        let frame_len = kernel_len(settings.min_frequency).next_power_of_two();
+
+        // the same plan the frames use, a complex kernel's spectrum is the real fft of its real part plus i
+        // times the real fft of its imaginary part
+        let fft = generator.get_forward_fft(frame_len);
+        let mut fft_input = fft.make_input_vec();
+        let mut fft_scratch = fft.make_scratch_vec();
+        let mut real_spectrum = fft.make_output_vec();
+        let mut imaginary_spectrum = fft.make_output_vec();
+
+        let kernels = (0..settings.bins())
+            .map(|bin| {
+                let frequency = settings.get_frequency_for_bin(bin);
+                let len = kernel_len(frequency);
+                let offset = (frame_len - len) / 2;
+                let window = settings.window_function.generate::<f64>(len);
+
+                let mut kernel = vec![Complex::<f64>::new(0.0, 0.0); frame_len];
+                for (index, weight) in window.iter().enumerate() {
+                    let phase = core::f64::consts::TAU * frequency * index as f64
+                        / settings.sample_rate as f64;
+                    kernel[offset + index] = Complex::from_polar(weight / len as f64, phase);
+                }
+
+                for (input, value) in fft_input.iter_mut().zip(&kernel) {
+                    *input = S::from(value.re).unwrap();
+                }
+                fft.process_with_scratch(&mut fft_input, &mut real_spectrum, &mut fft_scratch)
+                    .expect("fft buffers should always have the planned lengths");
+
+                for (input, value) in fft_input.iter_mut().zip(&kernel) {
+                    *input = S::from(value.im).unwrap();
+                }
+                fft.process_with_scratch(&mut fft_input, &mut imaginary_spectrum, &mut fft_scratch)
+                    .expect("fft buffers should always have the planned lengths");
+
+                // a real input only has the non-negative frequencies, which is where the kernel's energy is
+                let kernel = real_spectrum
+                    .iter()
+                    .zip(&imaginary_spectrum)
+                    .map(|(re, im)| *re + *im * Complex::i())
+                    .collect::<Vec<_>>();
+                let peak = kernel
+                    .iter()
+                    .map(|value| value.norm())
+                    .fold(S::zero(), S::max);
+                let threshold = peak * S::from(SPARSITY_THRESHOLD).unwrap();
+
+                let start = kernel
+                    .iter()
+                    .position(|value| value.norm() >= threshold)
+                    .unwrap_or(0);
+                let end = kernel
+                    .iter()
+                    .rposition(|value| value.norm() >= threshold)
+                    .map_or(start, |index| index + 1);
+
+                let scale = S::from(frame_len).unwrap();
+                (
+                    start,
+                    kernel[start..end]
+                        .iter()
+                        .map(|value| value.conj() / scale)
+                        .collect(),
+                )
+            })
+            .collect();
+
+        Self {
+            fft_input,
+            fft_output: fft.make_output_vec(),
+            fft_scratch,
+            settings,
+            frame_len,
+            kernels,
+            fft,
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+}
+
+impl<S: Float> FrameProcessor<S> for ConstantQProcessor<S> {
+    fn frame_len(&self) -> usize {
+        self.frame_len
+    }
+
+    fn hop_len(&self) -> usize {
+        self.settings.hop_len
+    }
+
+    fn bins(&self) -> usize {
+        self.kernels.len()
+    }
+
+    fn process_frame(&mut self, frame: &[S], out: &mut [S]) {
+        assert!(frame.len() <= self.frame_len);
+        assert_eq!(out.len(), self.bins());
+
+        for (index, input) in self.fft_input.iter_mut().enumerate() {
+            *input = frame.get(index).copied().unwrap_or(S::zero());
+        }
+
+        self.fft
+            .process_with_scratch(
+                &mut self.fft_input,
+                &mut self.fft_output,
+                &mut self.fft_scratch,
+            )
+            .expect("fft buffers should always have the planned lengths");
+
+        for (value, (start, kernel)) in out.iter_mut().zip(self.kernels.iter()) {
+            *value = self.fft_output[*start..]
+                .iter()
+                .zip(kernel.iter())
+                .fold(Complex::new(S::zero(), S::zero()), |acc, (bin, weight)| {
+                    acc + *bin * *weight
+                })
+                .norm();
+        }
+    }
+}
+
+pub type ConstantQIterator<S, T> = FrameIterator<S, T, ConstantQProcessor<S>>;
+
+impl<S: Float, T: Iterator<Item = S>> ConstantQIterator<S, T> {
+    pub fn new(wraps: T, settings: Settings, generator: &Generator<S>) -> Self {
+        Self::with_processor(wraps, ConstantQProcessor::new(settings, generator))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn sine_peaks_in_its_bin() {
+        let settings = Settings {
+            sample_rate: 8000,
+            min_frequency: 110.0,
+            bins_per_octave: 12,
+            n_octaves: 3,
+            hop_len: 512,
+            window_function: WindowFunction::Hann,
+        };
+
+        for bin in [0, 13, 30] {
+            let frequency = settings.get_frequency_for_bin(bin);
+            let mut processor = ConstantQProcessor::<f32>::new(settings.clone(), &Generator::new());
+            let frame = (0..processor.frame_len())
+                .map(|n| {
+                    num_traits::Float::sin(
+                        core::f64::consts::TAU * frequency * n as f64 / settings.sample_rate as f64,
+                    ) as f32
+                })
+                .collect::<Vec<_>>();
+
+            let mut out = vec![0.0; processor.bins()];
+            processor.process_frame(&frame, &mut out);
+
+            let loudest = (0..out.len())
+                .max_by(|l, r| out[*l].total_cmp(&out[*r]))
+                .unwrap();
+            assert_eq!(loudest, bin);
+            // half of a unit sine is at positive frequencies, and the kernels are normalised by the hann window's
+            // length rather than its sum, which is half that
+            assert!((out[bin] - 0.25).abs() < 0.01, "{}", out[bin]);
+        }
+    }
+}
@@ -0,0 +1,480 @@
+#[cfg(feature = "std")]
+pub(crate) use realfft::{ComplexToReal, RealToComplex};
+
+#[cfg(not(feature = "std"))]
+pub(crate) use portable::{ComplexToReal, RealToComplex};
+
+use alloc::sync::Arc;
+
+#[cfg(test)]
+use num_complex::Complex;
+
+use crate::spectrogram::Float;
+
+#[cfg(feature = "std")]
+pub(crate) fn plan_forward<T: Float>(len: usize) -> Arc<dyn RealToComplex<T>> {
+    realfft::RealFftPlanner::new().plan_fft_forward(len)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn plan_inverse<T: Float>(len: usize) -> Arc<dyn ComplexToReal<T>> {
+    realfft::RealFftPlanner::new().plan_fft_inverse(len)
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn plan_forward<T: Float>(len: usize) -> Arc<dyn RealToComplex<T>> {
+    Arc::new(portable::RealFft::new(len))
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn plan_inverse<T: Float>(len: usize) -> Arc<dyn ComplexToReal<T>> {
+    Arc::new(portable::RealFft::new(len))
+}
+
+/// An unnormalised forward fft of complex values, computed in place
+#[cfg(test)]
+pub(crate) struct ComplexFft<T: Float> {
+    #[cfg(feature = "std")]
+    fft: Arc<dyn rustfft::Fft<T>>,
+    #[cfg(not(feature = "std"))]
+    fft: portable::ComplexFft<T>,
+}
+
+#[cfg(test)]
+impl<T: Float> ComplexFft<T> {
+    #[cfg(feature = "std")]
+    pub(crate) fn new(len: usize) -> Self {
+        Self {
+            fft: rustfft::FftPlanner::new().plan_fft_forward(len),
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn new(len: usize) -> Self {
+        Self {
+            fft: portable::ComplexFft::new(len),
+        }
+    }
+
+    pub(crate) fn process(&self, values: &mut [Complex<T>]) {
+        self.fft.process(values);
+    }
+}
+
+/// Ffts for builds without std, where realfft isn't available.
+///
+/// Powers of two use an iterative radix-2 fft and every other length uses Bluestein's algorithm on top of one.
+/// Real transforms are computed as complex ones, which is slower than realfft but gives the same results.
+#[cfg(not(feature = "std"))]
+mod portable {
+    use alloc::{vec, vec::Vec};
+
+    use num_complex::Complex;
+
+    use crate::spectrogram::Float;
+
+    #[derive(Debug)]
+    pub struct FftError;
+
+    pub trait RealToComplex<T: Float>: Send + Sync {
+        fn process_with_scratch(
+            &self,
+            input: &mut [T],
+            output: &mut [Complex<T>],
+            scratch: &mut [Complex<T>],
+        ) -> Result<(), FftError>;
+
+        fn make_input_vec(&self) -> Vec<T>;
+
+        fn make_output_vec(&self) -> Vec<Complex<T>>;
+
+        fn make_scratch_vec(&self) -> Vec<Complex<T>>;
+    }
+
+    pub trait ComplexToReal<T: Float>: Send + Sync {
+        fn process_with_scratch(
+            &self,
+            input: &mut [Complex<T>],
+            output: &mut [T],
+            scratch: &mut [Complex<T>],
+        ) -> Result<(), FftError>;
+
+        fn make_input_vec(&self) -> Vec<Complex<T>>;
+
+        fn make_output_vec(&self) -> Vec<T>;
+
+        fn make_scratch_vec(&self) -> Vec<Complex<T>>;
+    }
+
+    fn polar<T: Float>(angle: f64) -> Complex<T> {
+        let (sin, cos) = num_traits::Float::sin_cos(angle);
+        Complex::new(T::from(cos).unwrap(), T::from(sin).unwrap())
+    }
+
+    /// Radix-2 fft for power of two lengths
+    struct Radix2<T: Float> {
+        /// `e^(-2πik/len)` for the first half of `k`
+        twiddles: Vec<Complex<T>>,
+    }
+
+    impl<T: Float> Radix2<T> {
+        fn new(len: usize) -> Self {
+            assert!(len.is_power_of_two());
+
+            Self {
+                twiddles: (0..len / 2)
+                    .map(|k| polar(-core::f64::consts::TAU * k as f64 / len as f64))
+                    .collect(),
+            }
+        }
+
+        fn process(&self, values: &mut [Complex<T>]) {
+            let len = values.len();
+            if len <= 1 {
+                return;
+            }
+
+            let bits = len.trailing_zeros();
+            for index in 0..len {
+                let reversed = index.reverse_bits() >> (usize::BITS - bits);
+                if index < reversed {
+                    values.swap(index, reversed);
+                }
+            }
+
+            let mut size = 2;
+            while size <= len {
+                let stride = len / size;
+                for chunk in values.chunks_exact_mut(size) {
+                    let (low, high) = chunk.split_at_mut(size / 2);
+                    for (index, (low, high)) in low.iter_mut().zip(high.iter_mut()).enumerate() {
+                        let product = *high * self.twiddles[index * stride];
+                        *high = *low - product;
+                        *low = *low + product;
+                    }
+                }
+                size *= 2;
+            }
+        }
+    }
+
+    /// Bluestein's algorithm, turning a fft of any length into a convolution done with power of two ffts
+    struct Bluestein<T: Float> {
+        inner: Radix2<T>,
+        /// `e^(-πik²/len)`
+        chirp: Vec<Complex<T>>,
+        /// Fft of the conjugate chirp, wrapped around to make the convolution circular
+        kernel: Vec<Complex<T>>,
+    }
+
+    impl<T: Float> Bluestein<T> {
+        fn new(len: usize) -> Self {
+            let inner_len = (2 * len - 1).next_power_of_two();
+            let inner = Radix2::new(inner_len);
+
+            // k² grows quickly, so it's reduced first to keep the angle accurate
+            let chirp = (0..len)
+                .map(|k| {
+                    let k_squared = (k * k) % (2 * len);
+                    polar(-core::f64::consts::PI * k_squared as f64 / len as f64)
+                })
+                .collect::<Vec<_>>();
+
+            let scale = T::from(inner_len).unwrap();
+            let mut kernel = vec![Complex::new(T::zero(), T::zero()); inner_len];
+            for (k, value) in chirp.iter().enumerate() {
+                kernel[k] = value.conj() / scale;
+                if k > 0 {
+                    kernel[inner_len - k] = value.conj() / scale;
+                }
+            }
+            inner.process(&mut kernel);
+
+            Self {
+                inner,
+                chirp,
+                kernel,
+            }
+        }
+
+        fn process(&self, values: &mut [Complex<T>], scratch: &mut [Complex<T>]) {
+            let scratch = &mut scratch[..self.kernel.len()];
+            scratch.fill(Complex::new(T::zero(), T::zero()));
+            for ((scratch, value), chirp) in scratch.iter_mut().zip(values.iter()).zip(&self.chirp)
+            {
+                *scratch = *value * *chirp;
+            }
+
+            self.inner.process(scratch);
+            // the inverse fft is the forward fft of the conjugate
+            for (scratch, kernel) in scratch.iter_mut().zip(&self.kernel) {
+                *scratch = (*scratch * *kernel).conj();
+            }
+            self.inner.process(scratch);
+
+            for ((value, scratch), chirp) in values.iter_mut().zip(scratch.iter()).zip(&self.chirp)
+            {
+                *value = scratch.conj() * *chirp;
+            }
+        }
+    }
+
+    pub struct ComplexFft<T: Float> {
+        len: usize,
+        algorithm: Algorithm<T>,
+    }
+
+    enum Algorithm<T: Float> {
+        Radix2(Radix2<T>),
+        Bluestein(Bluestein<T>),
+    }
+
+    impl<T: Float> ComplexFft<T> {
+        pub fn new(len: usize) -> Self {
+            Self {
+                len,
+                algorithm: if len.is_power_of_two() || len == 0 {
+                    Algorithm::Radix2(Radix2::new(len.max(1)))
+                } else {
+                    Algorithm::Bluestein(Bluestein::new(len))
+                },
+            }
+        }
+
+        fn scratch_len(&self) -> usize {
+            match &self.algorithm {
+                Algorithm::Radix2(_) => 0,
+                Algorithm::Bluestein(bluestein) => bluestein.kernel.len(),
+            }
+        }
+
+        fn process_with_scratch(&self, values: &mut [Complex<T>], scratch: &mut [Complex<T>]) {
+            assert_eq!(values.len(), self.len);
+
+            match &self.algorithm {
+                Algorithm::Radix2(radix2) => radix2.process(values),
+                Algorithm::Bluestein(bluestein) => bluestein.process(values, scratch),
+            }
+        }
+
+        #[cfg(test)]
+        pub fn process(&self, values: &mut [Complex<T>]) {
+            let mut scratch = vec![Complex::new(T::zero(), T::zero()); self.scratch_len()];
+            self.process_with_scratch(values, &mut scratch);
+        }
+    }
+
+    /// Real transforms of `len` values, using the first `len` values of the scratch as a complex buffer
+    pub struct RealFft<T: Float> {
+        fft: ComplexFft<T>,
+    }
+
+    impl<T: Float> RealFft<T> {
+        pub fn new(len: usize) -> Self {
+            Self {
+                fft: ComplexFft::new(len),
+            }
+        }
+
+        fn scratch_vec(&self) -> Vec<Complex<T>> {
+            vec![Complex::new(T::zero(), T::zero()); self.fft.len + self.fft.scratch_len()]
+        }
+    }
+
+    impl<T: Float> RealToComplex<T> for RealFft<T> {
+        fn process_with_scratch(
+            &self,
+            input: &mut [T],
+            output: &mut [Complex<T>],
+            scratch: &mut [Complex<T>],
+        ) -> Result<(), FftError> {
+            let len = self.fft.len;
+            if input.len() != len || output.len() != len / 2 + 1 || scratch.len() < len {
+                return Err(FftError);
+            }
+
+            let (values, scratch) = scratch.split_at_mut(len);
+            for (value, input) in values.iter_mut().zip(input.iter()) {
+                *value = Complex::new(*input, T::zero());
+            }
+
+            self.fft.process_with_scratch(values, scratch);
+            output.copy_from_slice(&values[..output.len()]);
+
+            Ok(())
+        }
+
+        fn make_input_vec(&self) -> Vec<T> {
+            vec![T::zero(); self.fft.len]
+        }
+
+        fn make_output_vec(&self) -> Vec<Complex<T>> {
+            vec![Complex::new(T::zero(), T::zero()); self.fft.len / 2 + 1]
+        }
+
+        fn make_scratch_vec(&self) -> Vec<Complex<T>> {
+            self.scratch_vec()
+        }
+    }
+
+    impl<T: Float> ComplexToReal<T> for RealFft<T> {
+        fn process_with_scratch(
+            &self,
+            input: &mut [Complex<T>],
+            output: &mut [T],
+            scratch: &mut [Complex<T>],
+        ) -> Result<(), FftError> {
+            let len = self.fft.len;
+            if input.len() != len / 2 + 1 || output.len() != len || scratch.len() < len {
+                return Err(FftError);
+            }
+
+            // the negative frequencies of a real signal mirror the positive ones, and conjugating before
+            // and after a forward fft makes it an inverse one
+            let (values, scratch) = scratch.split_at_mut(len);
+            for (index, value) in values.iter_mut().enumerate() {
+                *value = if index < input.len() {
+                    input[index].conj()
+                } else {
+                    input[len - index]
+                };
+            }
+
+            self.fft.process_with_scratch(values, scratch);
+            for (output, value) in output.iter_mut().zip(values.iter()) {
+                *output = value.re;
+            }
+
+            Ok(())
+        }
+
+        fn make_input_vec(&self) -> Vec<Complex<T>> {
+            vec![Complex::new(T::zero(), T::zero()); self.fft.len / 2 + 1]
+        }
+
+        fn make_output_vec(&self) -> Vec<T> {
+            vec![T::zero(); self.fft.len]
+        }
+
+        fn make_scratch_vec(&self) -> Vec<Complex<T>> {
+            self.scratch_vec()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use num_complex::Complex;
+
+    use super::*;
+
+    const LENGTHS: [usize; 17] = [
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 12, 16, 64, 100, 256, 1000, 1024, 4096,
+    ];
+
+    fn signal(len: usize) -> Vec<Complex<f64>> {
+        (0..len)
+            .map(|n| {
+                let n = n as f64;
+                Complex::new(
+                    num_traits::Float::sin(n * 0.7) + (n % 5.0) * 0.1,
+                    num_traits::Float::cos(n * 1.3),
+                )
+            })
+            .collect()
+    }
+
+    fn dft(values: &[Complex<f64>]) -> Vec<Complex<f64>> {
+        let len = values.len();
+        (0..len)
+            .map(|k| {
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(n, value)| {
+                        // reduced first so the angle stays accurate for long lengths
+                        let angle = -core::f64::consts::TAU * ((k * n) % len) as f64 / len as f64;
+                        let (sin, cos) = num_traits::Float::sin_cos(angle);
+                        value * Complex::new(cos, sin)
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: &[Complex<f64>], expected: &[Complex<f64>]) {
+        let len = expected.len();
+        assert_eq!(actual.len(), len);
+        let scale = expected
+            .iter()
+            .map(|value| value.norm())
+            .fold(1.0, f64::max);
+        for (index, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+            assert!(
+                (actual - expected).norm() < 1e-9 * scale,
+                "bin {index} of {len}: {actual} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn complex_fft_matches_dft() {
+        for len in LENGTHS {
+            let input = signal(len);
+            let mut output = input.clone();
+            ComplexFft::new(len).process(&mut output);
+
+            assert_close(&output, &dft(&input));
+        }
+    }
+
+    #[test]
+    fn real_fft_matches_dft() {
+        for len in LENGTHS {
+            let input = signal(len)
+                .iter()
+                .map(|value| Complex::new(value.re, 0.0))
+                .collect::<Vec<_>>();
+
+            let fft = plan_forward::<f64>(len);
+            let mut real = input.iter().map(|value| value.re).collect::<Vec<_>>();
+            let mut output = fft.make_output_vec();
+            let mut scratch = fft.make_scratch_vec();
+            fft.process_with_scratch(&mut real, &mut output, &mut scratch)
+                .unwrap();
+
+            assert_close(&output, &dft(&input)[..len / 2 + 1]);
+        }
+    }
+
+    #[test]
+    fn inverse_real_fft_undoes_forward() {
+        for len in LENGTHS {
+            let input = signal(len).iter().map(|value| value.re).collect::<Vec<_>>();
+
+            let forward = plan_forward::<f64>(len);
+            let mut spectrum = forward.make_output_vec();
+            let mut scratch = forward.make_scratch_vec();
+            forward
+                .process_with_scratch(&mut input.clone(), &mut spectrum, &mut scratch)
+                .unwrap();
+
+            let inverse = plan_inverse::<f64>(len);
+            let mut output = vec![0.0; len];
+            let mut scratch = inverse.make_scratch_vec();
+            inverse
+                .process_with_scratch(&mut spectrum, &mut output, &mut scratch)
+                .unwrap();
+
+            // the inverse is unnormalised
+            for (index, (output, input)) in output.iter().zip(&input).enumerate() {
+                assert!(
+                    (output / len as f64 - input).abs() < 1e-9,
+                    "sample {index} of {len}: {output} != {input}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use num_complex::Complex;
+
+use crate::{
+    fft::ComplexToReal,
+    spectrogram::{Float, FrameProcessor, Generator, Settings, SpectrogramProcessor},
+};
+
+/// Samples whose summed squared windows are below this fraction of the largest sum are left attenuated,
+/// rather than amplifying whatever error is there (mostly the very first and last samples)
+const WINDOW_SUM_FLOOR: f64 = 1e-3;
+
+/// Turns spectrogram columns back into samples by overlap-adding inverse ffts.
+///
+/// Each frame is weighted by the window again and the result divided by the summed squared windows,
+/// which is the least squares inverse of [`SpectrogramProcessor`] for any window and overlap.
+pub struct InverseProcessor<S: Float> {
+    settings: Settings,
+    window: Arc<Vec<S>>,
+    /// Undoes both the spectrogram's normalisation and the unnormalised inverse fft
+    scale: S,
+    fft: Arc<dyn ComplexToReal<S>>,
+    fft_input: Vec<Complex<S>>,
+    fft_output: Vec<S>,
+    fft_scratch: Vec<Complex<S>>,
+}
+
+impl<S: Float> InverseProcessor<S> {
+    pub fn new(settings: Settings, generator: &Generator<S>) -> Self {
+        assert!(
+            settings.window_len <= settings.fft_len,
+            "window length ({}) must not be longer than the fft length ({})",
+            settings.window_len,
+            settings.fft_len
+        );
+        assert!(
+            settings.fft_overlap < settings.window_len,
+            "fft overlap ({}) must be smaller than the window length ({})",
+            settings.fft_overlap,
+            settings.window_len
+        );
+
+        let fft = generator.get_inverse_fft(settings.fft_len);
+        let window = generator.get_window(settings.window_function, settings.window_len);
+
+        Self {
+            scale: S::from(window.len()).unwrap().sqrt() / S::from(settings.fft_len).unwrap(),
+            fft_input: fft.make_input_vec(),
+            fft_output: fft.make_output_vec(),
+            fft_scratch: fft.make_scratch_vec(),
+            settings,
+            window,
+            fft,
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Number of values in each spectrum, the same as the forward transform's bins
+    pub fn bins(&self) -> usize {
+        self.settings.fft_len / 2
+    }
+
+    /// Reconstructs `out` from a flat `[frames × bins]` buffer of complex spectra, such as those from
+    /// [`SpectrogramProcessor::process_frame_complex`], framed the same way as [`SpectrogramProcessor`].
+    pub fn process(&mut self, spectra: &[Complex<S>], out: &mut [S]) {
+        let bins = self.bins();
+        let hop_len = self.settings.hop_len();
+        let padding = self.settings.start_padding();
+        assert_eq!(spectra.len() % bins, 0, "spectra must be whole frames");
+
+        let mut window_sum = vec![S::zero(); out.len()];
+        out.fill(S::zero());
+
+        for (frame, spectrum) in spectra.chunks_exact(bins).enumerate() {
+            // the part of a frame over the start padding isn't part of the output
+            let start = frame * hop_len;
+            let skipped = padding.saturating_sub(start);
+            let start = start.saturating_sub(padding);
+            if start >= out.len() {
+                break;
+            }
+
+            // the forward transform drops the nyquist bin, and a real signal has no imaginary dc or nyquist
+            self.fft_input[..bins].copy_from_slice(spectrum);
+            self.fft_input[bins..].fill(Complex::new(S::zero(), S::zero()));
+            self.fft_input[0].im = S::zero();
+
+            self.fft
+                .process_with_scratch(
+                    &mut self.fft_input,
+                    &mut self.fft_output,
+                    &mut self.fft_scratch,
+                )
+                .expect("fft buffers should always have the planned lengths");
+
+            for ((sample, sum), (value, weight)) in out[start..]
+                .iter_mut()
+                .zip(window_sum[start..].iter_mut())
+                .zip(
+                    self.fft_output[..self.window.len()]
+                        .iter()
+                        .zip(self.window.iter())
+                        .skip(skipped),
+                )
+            {
+                *sample = *sample + *value * self.scale * *weight;
+                *sum = *sum + *weight * *weight;
+            }
+        }
+
+        let floor =
+            window_sum.iter().copied().fold(S::zero(), S::max) * S::from(WINDOW_SUM_FLOOR).unwrap();
+        for (sample, sum) in out.iter_mut().zip(window_sum) {
+            if sum > floor {
+                *sample = *sample / sum;
+            }
+        }
+    }
+}
+
+/// Relative rms error of passing `signal` through [`SpectrogramProcessor`] and back through
+/// [`InverseProcessor`], ignoring a window's length at each end where fewer frames overlap.
+///
+/// Close to zero when the squared windows overlap-add to a constant, and large when some samples are
+/// barely covered by any window, such as a hann window with no overlap.
+pub fn reconstruction_error<S: Float>(
+    signal: &[S],
+    settings: &Settings,
+    generator: &Generator<S>,
+) -> S {
+    let mut forward = SpectrogramProcessor::new(settings.clone(), generator);
+    let mut inverse = InverseProcessor::new(settings.clone(), generator);
+
+    let mut scratch = Vec::new();
+    let mut spectra = Vec::with_capacity(forward.frames(signal.len()) * forward.bins());
+    for frame in 0..forward.frames(signal.len()) {
+        let samples = forward.frame(signal, frame, &mut scratch);
+        spectra.extend_from_slice(forward.process_frame_complex(samples));
+    }
+
+    let mut reconstructed = vec![S::zero(); signal.len()];
+    inverse.process(&spectra, &mut reconstructed);
+
+    let interior = settings.window_len..signal.len().saturating_sub(settings.window_len);
+    let (error, energy) = signal
+        .get(interior.clone())
+        .unwrap_or_default()
+        .iter()
+        .zip(reconstructed.get(interior).unwrap_or_default())
+        .fold(
+            (S::zero(), S::zero()),
+            |(error, energy), (signal, reconstructed)| {
+                let difference = *signal - *reconstructed;
+                (error + difference * difference, energy + *signal * *signal)
+            },
+        );
+
+    if energy > S::zero() {
+        (error / energy).sqrt()
+    } else {
+        S::zero()
+    }
+}
+
+/// Estimates `n_samples` samples whose spectrogram has the given magnitudes, by alternating between
+/// the time and frequency domains and keeping the magnitudes fixed (Griffin & Lim).
+///
+/// `magnitudes` is a flat `[frames × bins]` buffer, as produced by [`SpectrogramProcessor`].
+pub fn griffin_lim<S: Float>(
+    magnitudes: &[S],
+    n_samples: usize,
+    settings: &Settings,
+    iterations: usize,
+    generator: &Generator<S>,
+) -> Vec<S> {
+    let mut forward = SpectrogramProcessor::new(settings.clone(), generator);
+    let mut inverse = InverseProcessor::new(settings.clone(), generator);
+
+    let bins = forward.bins();
+    let frames = magnitudes.len() / bins.max(1);
+    let mut scratch = Vec::new();
+
+    // start from zero phase, which is deterministic and converges about as well as random phase
+    let mut spectra = magnitudes
+        .iter()
+        .map(|magnitude| Complex::new(*magnitude, S::zero()))
+        .collect::<Vec<_>>();
+    let mut samples = vec![S::zero(); n_samples];
+
+    for _ in 0..iterations {
+        inverse.process(&spectra, &mut samples);
+
+        for (frame, (spectrum, magnitudes)) in spectra
+            .chunks_exact_mut(bins)
+            .zip(magnitudes.chunks_exact(bins))
+            .enumerate()
+            .take(frames)
+        {
+            let estimate = if frame < forward.frames(samples.len()) {
+                let samples = forward.frame(&samples, frame, &mut scratch);
+                forward.process_frame_complex(samples)
+            } else {
+                forward.process_frame_complex(&[])
+            };
+
+            for ((value, estimate), magnitude) in
+                spectrum.iter_mut().zip(estimate).zip(magnitudes.iter())
+            {
+                let norm = estimate.norm();
+                *value = if norm > S::zero() {
+                    *estimate * (*magnitude / norm)
+                } else {
+                    Complex::new(*magnitude, S::zero())
+                };
+            }
+        }
+    }
+
+    inverse.process(&spectra, &mut samples);
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::WindowFunction;
+
+    fn signal() -> Vec<f32> {
+        (0..8192)
+            .map(|n| {
+                let n = n as f32;
+                num_traits::Float::sin(n * 0.05) + 0.5 * num_traits::Float::sin(n * 0.31)
+            })
+            .collect()
+    }
+
+    fn settings(fft_overlap: usize) -> Settings {
+        Settings {
+            fft_len: 512,
+            window_len: 512,
+            fft_overlap,
+            window_function: WindowFunction::Hann,
+            center: false,
+        }
+    }
+
+    #[test]
+    fn cola_settings_reconstruct_the_interior() {
+        // squared hann windows overlap-add to a constant at 75% overlap
+        let error = reconstruction_error(&signal(), &settings(384), &Generator::new());
+        assert!(error < 1e-5, "{error}");
+    }
+
+    #[test]
+    fn non_cola_settings_are_reported() {
+        // without overlap, the samples at the edges of each hann window are barely covered
+        let error = reconstruction_error(&signal(), &settings(0), &Generator::new());
+        assert!(error > 1e-2, "{error}");
+    }
+
+    /// `|stft(signal)|` as a flat `[frames × bins]` buffer
+    fn magnitudes(signal: &[f32], settings: &Settings, generator: &Generator<f32>) -> Vec<f32> {
+        let mut forward = SpectrogramProcessor::new(settings.clone(), generator);
+        let mut scratch = Vec::new();
+
+        (0..forward.frames(signal.len()))
+            .flat_map(|frame| {
+                let samples = forward.frame(signal, frame, &mut scratch);
+                forward
+                    .process_frame_complex(samples)
+                    .iter()
+                    .map(|value| value.norm())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn griffin_lim_converges_on_the_magnitudes() {
+        let signal = signal();
+        let settings = settings(384);
+        let generator = Generator::new();
+        let target = magnitudes(&signal, &settings, &generator);
+
+        // relative distance between the target magnitudes and those of the estimate
+        let spectral_error = |iterations| {
+            let estimate = griffin_lim(&target, signal.len(), &settings, iterations, &generator);
+            let (error, energy) = magnitudes(&estimate, &settings, &generator)
+                .iter()
+                .zip(&target)
+                .fold((0.0, 0.0), |(error, energy), (estimate, target)| {
+                    let difference = estimate - target;
+                    (error + difference * difference, energy + target * target)
+                });
+
+            num_traits::Float::sqrt(error / energy)
+        };
+
+        let errors = [0, 4, 16, 64].map(spectral_error);
+        assert!(
+            errors.windows(2).all(|pair| pair[1] < pair[0]),
+            "{errors:?}"
+        );
+        assert!(errors[3] < 0.1, "{errors:?}");
+    }
+}
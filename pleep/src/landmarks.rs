@@ -0,0 +1,152 @@
+use alloc::vec::Vec;
+
+use crate::{peaks::Peak, spectrogram::Float};
+
+const BIN_BITS: u32 = 10;
+const TIME_DELTA_BITS: u32 = 12;
+
+/// Largest bin that fits in a [`hash`]
+pub const MAX_BIN: usize = (1 << BIN_BITS) - 1;
+/// Largest number of columns between peaks that fits in a [`hash`]
+pub const MAX_TIME_DELTA: usize = (1 << TIME_DELTA_BITS) - 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Maximum number of targets paired with each anchor peak
+    pub fan_out: usize,
+    /// Smallest number of columns between an anchor and its targets
+    pub min_time_delta: usize,
+    /// Largest number of columns between an anchor and its targets
+    pub max_time_delta: usize,
+    /// Largest number of bins between an anchor and its targets
+    pub max_frequency_delta: usize,
+}
+
+/// A hashed pair of peaks, anchored at the column of the earlier peak
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Landmark {
+    pub hash: u32,
+    pub frame: u32,
+}
+
+/// Packs an anchor bin, target bin and the columns between them into a single hash.
+///
+/// Bins use 10 bits and the time delta 12 bits, so they must be at most [`MAX_BIN`] and
+/// [`MAX_TIME_DELTA`].
+pub fn hash(anchor_bin: usize, target_bin: usize, time_delta: usize) -> u32 {
+    assert!(
+        anchor_bin <= MAX_BIN && target_bin <= MAX_BIN,
+        "bins ({anchor_bin}, {target_bin}) must be at most {MAX_BIN} to fit in a hash"
+    );
+    assert!(
+        time_delta <= MAX_TIME_DELTA,
+        "time delta ({time_delta}) must be at most {MAX_TIME_DELTA} to fit in a hash"
+    );
+
+    ((anchor_bin as u32) << (BIN_BITS + TIME_DELTA_BITS))
+        | ((target_bin as u32) << TIME_DELTA_BITS)
+        | time_delta as u32
+}
+
+/// Pairs each peak with the following peaks in its target zone.
+///
+/// `peaks` must be ordered by frame, as returned by [`crate::peaks::find_peaks`], and have bins up to
+/// [`MAX_BIN`]. `settings.max_time_delta` must be at most [`MAX_TIME_DELTA`].
+pub fn find_landmarks<S: Float>(peaks: &[Peak<S>], settings: &Settings) -> Vec<Landmark> {
+    let mut landmarks = Vec::new();
+
+    for (index, anchor) in peaks.iter().enumerate() {
+        let targets = peaks[index + 1..]
+            .iter()
+            .take_while(|target| target.frame - anchor.frame <= settings.max_time_delta)
+            .filter(|target| target.frame - anchor.frame >= settings.min_time_delta)
+            .filter(|target| target.bin.abs_diff(anchor.bin) <= settings.max_frequency_delta)
+            .take(settings.fan_out);
+
+        for target in targets {
+            landmarks.push(Landmark {
+                hash: hash(anchor.bin, target.bin, target.frame - anchor.frame),
+                frame: anchor.frame as u32,
+            });
+        }
+    }
+
+    landmarks
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    fn peak(frame: usize, bin: usize) -> Peak<f32> {
+        Peak {
+            frame,
+            bin,
+            magnitude: 1.0,
+        }
+    }
+
+    #[test]
+    fn hash_packs_each_field_into_its_own_bits() {
+        assert_eq!(hash(0, 0, 0), 0);
+        assert_eq!(hash(1, 0, 0), 1 << 22);
+        assert_eq!(hash(0, 1, 0), 1 << 12);
+        assert_eq!(hash(0, 0, 1), 1);
+        assert_eq!(hash(1023, 1023, 4095), u32::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "bins")]
+    fn hash_rejects_bins_that_do_not_fit() {
+        hash(0, MAX_BIN + 1, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "time delta")]
+    fn hash_rejects_time_deltas_that_do_not_fit() {
+        hash(0, 0, MAX_TIME_DELTA + 1);
+    }
+
+    #[test]
+    fn only_peaks_in_the_target_zone_are_paired() {
+        let settings = Settings {
+            fan_out: 2,
+            min_time_delta: 2,
+            max_time_delta: 5,
+            max_frequency_delta: 10,
+        };
+        let peaks = vec![
+            peak(0, 100),
+            // too soon after the anchor
+            peak(1, 100),
+            // too far away in frequency
+            peak(3, 111),
+            peak(4, 90),
+            peak(5, 105),
+            // past the fan out, and then too late
+            peak(5, 106),
+            peak(6, 100),
+        ];
+
+        let anchored_at_zero = find_landmarks(&peaks, &settings)
+            .into_iter()
+            .filter(|landmark| landmark.frame == 0)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            anchored_at_zero,
+            [
+                Landmark {
+                    hash: hash(100, 90, 4),
+                    frame: 0
+                },
+                Landmark {
+                    hash: hash(100, 105, 5),
+                    frame: 0
+                },
+            ]
+        );
+    }
+}
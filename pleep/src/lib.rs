@@ -1 +1,22 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!(
+    "pleep needs either the `std` feature, or the `libm` feature for float maths without std"
+);
+
+extern crate alloc;
+
+pub mod chroma;
+pub mod cqt;
+mod fft;
+pub mod inverse;
+pub mod landmarks;
+pub mod magnitude;
+pub mod mel;
+pub mod mfcc;
+pub mod multires;
+pub mod onset;
+pub mod peaks;
 pub mod spectrogram;
+pub mod window;
@@ -0,0 +1,91 @@
+use crate::spectrogram::Float;
+
+/// How spectrogram magnitudes are compressed before they are compared
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum MagnitudeScale {
+    /// The magnitudes are left as they are
+    #[default]
+    Linear,
+    /// The squared magnitude
+    Power,
+    /// `20 * log10(magnitude / reference)`, never going below `floor` decibels
+    Decibels { reference: f32, floor: f32 },
+    /// `ln(1 + gain * magnitude)`
+    Log1p { gain: f32 },
+}
+
+impl MagnitudeScale {
+    pub fn apply<S: Float>(&self, magnitude: S) -> S {
+        match self {
+            MagnitudeScale::Linear => magnitude,
+            MagnitudeScale::Power => magnitude * magnitude,
+            MagnitudeScale::Decibels { reference, floor } => {
+                let decibels =
+                    S::from(20.0).unwrap() * (magnitude / S::from(*reference).unwrap()).log10();
+                // a silent bin gives -inf (or nan for a zero reference), both of which become the floor
+                decibels.max(S::from(*floor).unwrap())
+            }
+            MagnitudeScale::Log1p { gain } => (S::from(*gain).unwrap() * magnitude).ln_1p(),
+        }
+    }
+
+    /// Undoes [`Self::apply`], values clamped to the decibel floor come back as the floor's magnitude
+    pub fn invert<S: Float>(&self, value: S) -> S {
+        match self {
+            MagnitudeScale::Linear => value,
+            MagnitudeScale::Power => value.max(S::zero()).sqrt(),
+            MagnitudeScale::Decibels { reference, .. } => {
+                S::from(*reference).unwrap()
+                    * S::from(10.0).unwrap().powf(value / S::from(20.0).unwrap())
+            }
+            MagnitudeScale::Log1p { gain } => value.exp_m1() / S::from(*gain).unwrap(),
+        }
+    }
+
+    pub fn apply_in_place<S: Float>(&self, column: &mut [S]) {
+        if let MagnitudeScale::Linear = self {
+            return;
+        }
+
+        for value in column.iter_mut() {
+            *value = self.apply(*value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_then_invert_round_trips() {
+        for scale in [
+            MagnitudeScale::Linear,
+            MagnitudeScale::Power,
+            MagnitudeScale::Decibels {
+                reference: 2.0,
+                floor: -80.0,
+            },
+            MagnitudeScale::Log1p { gain: 10.0 },
+        ] {
+            for magnitude in [0.01f64, 0.5, 1.0, 3.0, 100.0] {
+                let round_trip = scale.invert(scale.apply(magnitude));
+                assert!(
+                    (round_trip - magnitude).abs() < 1e-5 * magnitude,
+                    "{scale:?} {magnitude}: {round_trip}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn silence_is_raised_to_the_decibel_floor() {
+        let scale = MagnitudeScale::Decibels {
+            reference: 1.0,
+            floor: -60.0,
+        };
+
+        assert_eq!(scale.apply(0.0f64), -60.0);
+        assert!((scale.invert(scale.apply(0.0f64)) - 1e-3).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,227 @@
+use alloc::{vec, vec::Vec};
+
+#[cfg(not(feature = "std"))]
+#[allow(
+    unused_imports,
+    reason = "unused when std is linked anyway, as it is for tests"
+)]
+use num_traits::Float as _;
+
+use crate::spectrogram::{get_frequency_for_bin, Float, SpectrogramIterator};
+
+/// Which formula to use when converting between hz and mels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MelScale {
+    /// `2595 * log10(1 + hz / 700)`, with filters peaking at 1
+    #[default]
+    Htk,
+    /// Slaney's auditory toolbox scale (linear below 1khz, log above), with area normalised filters
+    Slaney,
+}
+
+const SLANEY_MIN_LOG_HZ: f64 = 1000.0;
+const SLANEY_HZ_PER_MEL: f64 = 200.0 / 3.0;
+const SLANEY_MIN_LOG_MEL: f64 = SLANEY_MIN_LOG_HZ / SLANEY_HZ_PER_MEL;
+
+fn slaney_log_step() -> f64 {
+    6.4f64.ln() / 27.0
+}
+
+impl MelScale {
+    pub fn hz_to_mel(&self, hz: f64) -> f64 {
+        match self {
+            MelScale::Htk => 2595.0 * (1.0 + hz / 700.0).log10(),
+            MelScale::Slaney => {
+                if hz < SLANEY_MIN_LOG_HZ {
+                    hz / SLANEY_HZ_PER_MEL
+                } else {
+                    SLANEY_MIN_LOG_MEL + (hz / SLANEY_MIN_LOG_HZ).ln() / slaney_log_step()
+                }
+            }
+        }
+    }
+
+    pub fn mel_to_hz(&self, mel: f64) -> f64 {
+        match self {
+            MelScale::Htk => 700.0 * (10f64.powf(mel / 2595.0) - 1.0),
+            MelScale::Slaney => {
+                if mel < SLANEY_MIN_LOG_MEL {
+                    mel * SLANEY_HZ_PER_MEL
+                } else {
+                    SLANEY_MIN_LOG_HZ * (slaney_log_step() * (mel - SLANEY_MIN_LOG_MEL)).exp()
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub n_mels: usize,
+    pub min_frequency: f64,
+    pub max_frequency: f64,
+    pub scale: MelScale,
+}
+
+/// Triangular filters mapping linear spectrogram columns onto the mel scale
+#[derive(Debug, Clone)]
+pub struct MelFilterbank<S: Float> {
+    /// The first bin each filter covers, and its weights from that bin onwards
+    filters: Vec<(usize, Vec<S>)>,
+}
+
+impl<S: Float> MelFilterbank<S> {
+    /// Creates a filterbank for columns produced from ffts of length `fft_len` at `sample_rate`
+    pub fn new(settings: &Settings, sample_rate: usize, fft_len: usize) -> Self {
+        let scale = settings.scale;
+        let min_mel = scale.hz_to_mel(settings.min_frequency);
+        let max_mel = scale.hz_to_mel(settings.max_frequency);
+
+        let edges = (0..settings.n_mels + 2)
+            .map(|index| {
+                let mel =
+                    min_mel + (max_mel - min_mel) * index as f64 / (settings.n_mels + 1) as f64;
+                scale.mel_to_hz(mel)
+            })
+            .collect::<Vec<_>>();
+
+        let bins = fft_len / 2;
+
+        let filters = edges
+            .windows(3)
+            .map(|edges| {
+                let (lower, centre, upper) = (edges[0], edges[1], edges[2]);
+
+                let normalisation = match scale {
+                    MelScale::Htk => 1.0,
+                    MelScale::Slaney => 2.0 / (upper - lower),
+                };
+
+                let weights = (0..bins)
+                    .map(|bin| {
+                        let frequency = get_frequency_for_bin(bin, sample_rate, fft_len);
+                        let rising = (frequency - lower) / (centre - lower);
+                        let falling = (upper - frequency) / (upper - centre);
+
+                        rising.min(falling).max(0.0) * normalisation
+                    })
+                    .collect::<Vec<_>>();
+
+                let start = weights.iter().position(|w| *w > 0.0).unwrap_or(bins);
+                let end = weights
+                    .iter()
+                    .rposition(|w| *w > 0.0)
+                    .map_or(start, |index| index + 1);
+
+                (
+                    start,
+                    weights[start..end]
+                        .iter()
+                        .map(|w| S::from(*w).unwrap())
+                        .collect(),
+                )
+            })
+            .collect();
+
+        Self { filters }
+    }
+
+    /// Number of values in each output column
+    pub fn n_mels(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// Applies the filterbank to `column`, writing [`Self::n_mels`] values into `out`
+    pub fn apply_into(&self, column: &[S], out: &mut [S]) {
+        assert_eq!(out.len(), self.n_mels());
+
+        for (value, (start, weights)) in out.iter_mut().zip(self.filters.iter()) {
+            *value = column
+                .iter()
+                .skip(*start)
+                .zip(weights.iter())
+                .fold(S::zero(), |acc, (bin, weight)| acc + *bin * *weight);
+        }
+    }
+
+    pub fn apply(&self, column: &[S]) -> Vec<S> {
+        let mut out = vec![S::zero(); self.n_mels()];
+        self.apply_into(column, &mut out);
+        out
+    }
+}
+
+pub struct MelSpectrogramIterator<S: Float, T: Iterator<Item = S>> {
+    inner: SpectrogramIterator<S, T>,
+    filterbank: MelFilterbank<S>,
+}
+
+impl<S: Float, T: Iterator<Item = S>> MelSpectrogramIterator<S, T> {
+    pub fn new(spectrogram: SpectrogramIterator<S, T>, filterbank: MelFilterbank<S>) -> Self {
+        Self {
+            inner: spectrogram,
+            filterbank,
+        }
+    }
+}
+
+impl<S: Float, T: Iterator<Item = S>> Iterator for MelSpectrogramIterator<S, T> {
+    type Item = Vec<S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|col| self.filterbank.apply(&col))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_peak_at_their_centre_frequencies() {
+        let settings = Settings {
+            n_mels: 10,
+            min_frequency: 0.0,
+            max_frequency: 400.0,
+            scale: MelScale::Htk,
+        };
+        // one hz per bin, so every centre frequency is within half a bin of one
+        let filterbank = MelFilterbank::<f64>::new(&settings, 1000, 1000);
+        let (min_mel, max_mel) = (
+            settings.scale.hz_to_mel(settings.min_frequency),
+            settings.scale.hz_to_mel(settings.max_frequency),
+        );
+
+        for index in 0..settings.n_mels {
+            let centre = settings.scale.mel_to_hz(
+                min_mel + (max_mel - min_mel) * (index + 1) as f64 / (settings.n_mels + 1) as f64,
+            );
+
+            let mut column = vec![0.0; 500];
+            column[centre.round() as usize] = 1.0;
+            let out = filterbank.apply(&column);
+
+            let loudest = (0..out.len())
+                .max_by(|l, r| out[*l].total_cmp(&out[*r]))
+                .unwrap();
+            assert_eq!(loudest, index, "{out:?}");
+            assert!(out[index] > 0.95, "{out:?}");
+        }
+    }
+
+    #[test]
+    fn scales_round_trip() {
+        for scale in [MelScale::Htk, MelScale::Slaney] {
+            for hz in [0.0, 200.0, 999.0, 1000.0, 4000.0] {
+                let round_trip = scale.mel_to_hz(scale.hz_to_mel(hz));
+                assert!(
+                    (round_trip - hz).abs() < 1e-6,
+                    "{scale:?} {hz}: {round_trip}"
+                );
+            }
+        }
+
+        // slaney's linear and log parts meet at 1khz
+        assert!((MelScale::Slaney.hz_to_mel(SLANEY_MIN_LOG_HZ) - 15.0).abs() < 1e-9);
+    }
+}
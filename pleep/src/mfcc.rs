@@ -0,0 +1,228 @@
+use alloc::{vec, vec::Vec};
+
+#[cfg(not(feature = "std"))]
+#[allow(
+    unused_imports,
+    reason = "unused when std is linked anyway, as it is for tests"
+)]
+use num_traits::Float as _;
+
+use crate::{
+    mel::{self, MelFilterbank},
+    spectrogram::Float,
+};
+
+/// Smallest mel energy before taking the log, to avoid `ln(0)`
+const LOG_FLOOR: f64 = 1e-10;
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub mel: mel::Settings,
+    pub n_coefficients: usize,
+    /// 0 for just the coefficients, 1 to also append deltas, 2 to also append delta-deltas
+    pub delta_order: usize,
+    /// Number of frames either side used when computing deltas
+    pub delta_width: usize,
+}
+
+/// Turns linear spectrogram columns into mel frequency cepstral coefficients
+#[derive(Debug, Clone)]
+pub struct Mfcc<S: Float> {
+    filterbank: MelFilterbank<S>,
+    /// `[n_coefficients × n_mels]` orthonormal dct-II matrix
+    dct: Vec<S>,
+    mel_scratch: Vec<S>,
+    n_coefficients: usize,
+    delta_order: usize,
+    delta_width: usize,
+}
+
+impl<S: Float> Mfcc<S> {
+    pub fn new(settings: &Settings, sample_rate: usize, fft_len: usize) -> Self {
+        let filterbank = MelFilterbank::new(&settings.mel, sample_rate, fft_len);
+        let n_mels = filterbank.n_mels();
+
+        assert!(
+            settings.n_coefficients <= n_mels,
+            "cannot take {} coefficients from {} mel bands",
+            settings.n_coefficients,
+            n_mels
+        );
+
+        let mut dct = Vec::with_capacity(settings.n_coefficients * n_mels);
+        for k in 0..settings.n_coefficients {
+            let scale = if k == 0 {
+                (1.0 / n_mels as f64).sqrt()
+            } else {
+                (2.0 / n_mels as f64).sqrt()
+            };
+
+            for m in 0..n_mels {
+                let angle = core::f64::consts::PI * k as f64 * (m as f64 + 0.5) / n_mels as f64;
+                dct.push(S::from(scale * angle.cos()).unwrap());
+            }
+        }
+
+        Self {
+            mel_scratch: vec![S::zero(); n_mels],
+            filterbank,
+            dct,
+            n_coefficients: settings.n_coefficients,
+            delta_order: settings.delta_order,
+            delta_width: settings.delta_width,
+        }
+    }
+
+    pub fn n_coefficients(&self) -> usize {
+        self.n_coefficients
+    }
+
+    /// Length of each full vector, including any deltas
+    pub fn height(&self) -> usize {
+        self.n_coefficients * (self.delta_order + 1)
+    }
+
+    /// Writes the coefficients of `column` into `out`, which must be [`Self::n_coefficients`] long
+    pub fn apply_into(&mut self, column: &[S], out: &mut [S]) {
+        assert_eq!(out.len(), self.n_coefficients);
+
+        self.filterbank.apply_into(column, &mut self.mel_scratch);
+
+        let floor = S::from(LOG_FLOOR).unwrap();
+        for value in self.mel_scratch.iter_mut() {
+            *value = value.max(floor).ln();
+        }
+
+        for (coefficient, row) in out
+            .iter_mut()
+            .zip(self.dct.chunks_exact(self.mel_scratch.len()))
+        {
+            *coefficient = row
+                .iter()
+                .zip(self.mel_scratch.iter())
+                .fold(S::zero(), |acc, (weight, value)| acc + *weight * *value);
+        }
+    }
+
+    /// Fills in the deltas of a flat `[frames × height]` buffer, where the first
+    /// [`Self::n_coefficients`] values of each vector have already been written by [`Self::apply_into`]
+    pub fn fill_deltas(&self, vectors: &mut [S]) {
+        self.fill_deltas_at(vectors, self.height(), 0);
+    }
+
+    /// Like [`Self::fill_deltas`], for vectors of `stride` values where these coefficients start at `offset`
+    pub fn fill_deltas_at(&self, vectors: &mut [S], stride: usize, offset: usize) {
+        for order in 1..=self.delta_order {
+            fill_deltas(
+                vectors,
+                stride,
+                offset + (order - 1) * self.n_coefficients,
+                offset + order * self.n_coefficients,
+                self.n_coefficients,
+                self.delta_width,
+            );
+        }
+    }
+}
+
+/// Computes regression deltas over time of `len` values starting at `source` in each vector,
+/// writing them `len` values starting at `destination`. Frames past either end repeat the edge frame.
+pub fn fill_deltas<S: Float>(
+    vectors: &mut [S],
+    stride: usize,
+    source: usize,
+    destination: usize,
+    len: usize,
+    width: usize,
+) {
+    let frames = vectors.len() / stride;
+    if frames == 0 || width == 0 {
+        return;
+    }
+
+    let denominator = S::from(2 * (1..=width).map(|n| n * n).sum::<usize>()).unwrap();
+
+    for frame in 0..frames {
+        for index in 0..len {
+            let mut total = S::zero();
+
+            for n in 1..=width {
+                let after = (frame + n).min(frames - 1);
+                let before = frame.saturating_sub(n);
+
+                total = total
+                    + S::from(n).unwrap()
+                        * (vectors[after * stride + source + index]
+                            - vectors[before * stride + source + index]);
+            }
+
+            vectors[frame * stride + destination + index] = total / denominator;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mfcc(delta_order: usize) -> Mfcc<f64> {
+        Mfcc::new(
+            &Settings {
+                mel: mel::Settings {
+                    n_mels: 20,
+                    min_frequency: 0.0,
+                    max_frequency: 4000.0,
+                    scale: mel::MelScale::Htk,
+                },
+                n_coefficients: 13,
+                delta_order,
+                delta_width: 2,
+            },
+            8000,
+            512,
+        )
+    }
+
+    #[test]
+    fn dct_of_a_constant_only_has_c0() {
+        let mfcc = mfcc(0);
+        let n_mels = mfcc.mel_scratch.len();
+
+        for (k, row) in mfcc.dct.chunks_exact(n_mels).enumerate() {
+            let coefficient = row.iter().map(|weight| weight * 2.0).sum::<f64>();
+            let expected = if k == 0 {
+                2.0 * (n_mels as f64).sqrt()
+            } else {
+                0.0
+            };
+            assert!((coefficient - expected).abs() < 1e-9, "c{k}: {coefficient}");
+        }
+    }
+
+    #[test]
+    fn deltas_of_a_ramp_are_its_slope() {
+        let mfcc = mfcc(2);
+        let height = mfcc.height();
+        let frames = 12;
+
+        let mut vectors = vec![0.0; frames * height];
+        for (frame, vector) in vectors.chunks_exact_mut(height).enumerate() {
+            vector[..mfcc.n_coefficients()].fill(3.0 * frame as f64);
+        }
+        mfcc.fill_deltas(&mut vectors);
+
+        // the repeated edge frames bend the ramp's deltas two frames in, and so its delta-deltas four frames in
+        for vector in vectors.chunks_exact(height).take(frames - 4).skip(4) {
+            let (deltas, delta_deltas) =
+                vector[mfcc.n_coefficients()..].split_at(mfcc.n_coefficients());
+            assert!(
+                deltas.iter().all(|delta| (delta - 3.0).abs() < 1e-9),
+                "{deltas:?}"
+            );
+            assert!(
+                delta_deltas.iter().all(|delta| delta.abs() < 1e-9),
+                "{delta_deltas:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    spectrogram::{self, Float, FrameIterator, FrameProcessor, Generator, SpectrogramProcessor},
+    window::WindowFunction,
+};
+
+/// How the columns of each resolution are turned into one column
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Combine {
+    /// One after the other, in the order of the window lengths
+    #[default]
+    Stack,
+    /// The mean magnitude of each bin across every resolution
+    Mean,
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Shared by every resolution so they all have the same bins, must be at least the longest window
+    pub fft_len: usize,
+    pub window_lens: Vec<usize>,
+    /// Number of samples between the centres of consecutive frames, shared by every resolution
+    pub hop_len: usize,
+    pub window_function: WindowFunction,
+    /// Pad half of the longest window before the first sample, so frame `i` is centred on sample `i * hop_len`
+    pub center: bool,
+    pub combine: Combine,
+}
+
+/// Computes spectrograms with several window lengths on the same hop grid, with every window of a
+/// frame sharing the same centre
+pub struct MultiResolutionProcessor<S: Float> {
+    settings: Settings,
+    frame_len: usize,
+    processors: Vec<SpectrogramProcessor<S>>,
+    scratch: Vec<S>,
+}
+
+impl<S: Float> MultiResolutionProcessor<S> {
+    pub fn new(settings: Settings, generator: &Generator<S>) -> Self {
+        assert!(
+            !settings.window_lens.is_empty(),
+            "need at least one window length"
+        );
+        assert!(settings.hop_len > 0, "hop length must be positive");
+
+        let processors = settings
+            .window_lens
+            .iter()
+            .map(|window_len| {
+                SpectrogramProcessor::new(
+                    spectrogram::Settings {
+                        fft_len: settings.fft_len,
+                        window_len: *window_len,
+                        // frames are cut by this processor, so the inner hops are never used
+                        fft_overlap: 0,
+                        window_function: settings.window_function,
+                        center: false,
+                    },
+                    generator,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            frame_len: settings.window_lens.iter().copied().max().unwrap(),
+            scratch: vec![S::zero(); settings.fft_len / 2],
+            settings,
+            processors,
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Number of bins in each resolution's column
+    pub fn resolution_bins(&self) -> usize {
+        self.settings.fft_len / 2
+    }
+}
+
+impl<S: Float> FrameProcessor<S> for MultiResolutionProcessor<S> {
+    fn frame_len(&self) -> usize {
+        self.frame_len
+    }
+
+    fn hop_len(&self) -> usize {
+        self.settings.hop_len
+    }
+
+    fn start_padding(&self) -> usize {
+        if self.settings.center {
+            self.frame_len / 2
+        } else {
+            0
+        }
+    }
+
+    fn bins(&self) -> usize {
+        match self.settings.combine {
+            Combine::Stack => self.resolution_bins() * self.processors.len(),
+            Combine::Mean => self.resolution_bins(),
+        }
+    }
+
+    fn process_frame(&mut self, frame: &[S], out: &mut [S]) {
+        assert!(frame.len() <= self.frame_len);
+        assert_eq!(out.len(), self.bins());
+
+        let bins = self.resolution_bins();
+        if let Combine::Mean = self.settings.combine {
+            out.fill(S::zero());
+        }
+
+        for (index, processor) in self.processors.iter_mut().enumerate() {
+            // shorter windows are taken from the middle of the frame, so every window has the same centre
+            let offset = (self.frame_len - processor.frame_len()) / 2;
+            let start = offset.min(frame.len());
+            let end = (offset + processor.frame_len()).min(frame.len());
+
+            match self.settings.combine {
+                Combine::Stack => processor.process_frame(
+                    &frame[start..end],
+                    &mut out[index * bins..(index + 1) * bins],
+                ),
+                Combine::Mean => {
+                    processor.process_frame(&frame[start..end], &mut self.scratch);
+                    for (value, magnitude) in out.iter_mut().zip(self.scratch.iter()) {
+                        *value = *value + *magnitude;
+                    }
+                }
+            }
+        }
+
+        if let Combine::Mean = self.settings.combine {
+            let count = S::from(self.processors.len()).unwrap();
+            for value in out.iter_mut() {
+                *value = *value / count;
+            }
+        }
+    }
+}
+
+pub type MultiResolutionIterator<S, T> = FrameIterator<S, T, MultiResolutionProcessor<S>>;
+
+impl<S: Float, T: Iterator<Item = S>> MultiResolutionIterator<S, T> {
+    pub fn new(wraps: T, settings: Settings, generator: &Generator<S>) -> Self {
+        Self::with_processor(wraps, MultiResolutionProcessor::new(settings, generator))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FFT_LEN: usize = 1024;
+    const HOP_LEN: usize = 128;
+
+    fn signal() -> Vec<f32> {
+        (0..4096)
+            .map(|n| {
+                let n = n as f32;
+                num_traits::Float::sin(n * 0.07) + 0.3 * num_traits::Float::sin(n * 0.9)
+            })
+            .collect()
+    }
+
+    fn multires(window_lens: Vec<usize>, combine: Combine) -> (Vec<f32>, usize) {
+        let mut processor = MultiResolutionProcessor::new(
+            Settings {
+                fft_len: FFT_LEN,
+                window_lens,
+                hop_len: HOP_LEN,
+                window_function: WindowFunction::Hann,
+                center: false,
+                combine,
+            },
+            &Generator::new(),
+        );
+        let mut out = Vec::new();
+        processor.process_into_vec(&signal(), &mut out);
+        (out, processor.bins())
+    }
+
+    fn single(window_len: usize) -> Vec<f32> {
+        let mut processor = SpectrogramProcessor::new(
+            spectrogram::Settings {
+                fft_len: FFT_LEN,
+                window_len,
+                fft_overlap: window_len - HOP_LEN,
+                window_function: WindowFunction::Hann,
+                center: false,
+            },
+            &Generator::new(),
+        );
+        let mut out = Vec::new();
+        processor.process_into_vec(&signal(), &mut out);
+        out
+    }
+
+    #[test]
+    fn stacks_a_column_for_every_window() {
+        let (stacked, bins) = multires(vec![1024, 512, 256], Combine::Stack);
+        assert_eq!(bins, 3 * FFT_LEN / 2);
+
+        // with the longest window first, its columns line up with a single resolution's
+        let longest = single(1024);
+        assert_eq!(stacked.len() / bins, longest.len() / (FFT_LEN / 2));
+        for (stacked, longest) in stacked
+            .chunks_exact(bins)
+            .zip(longest.chunks_exact(FFT_LEN / 2))
+        {
+            assert_eq!(&stacked[..FFT_LEN / 2], longest);
+        }
+    }
+
+    #[test]
+    fn mean_of_equal_windows_is_a_single_resolution() {
+        let (mean, bins) = multires(vec![512, 512], Combine::Mean);
+        assert_eq!(bins, FFT_LEN / 2);
+
+        let single = single(512);
+        assert_eq!(mean.len(), single.len());
+        for (mean, single) in mean.iter().zip(&single) {
+            assert!((mean - single).abs() < 1e-5, "{mean} {single}");
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use alloc::{vec, vec::Vec};
+
+use num_complex::Complex;
+
+use crate::spectrogram::{self, Float, FrameProcessor, Generator, SpectrogramProcessor};
+
+/// How much each column of a spectrogram looks like the start of a new sound
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoveltyFunction {
+    /// Sum of the increases in magnitude since the previous column
+    #[default]
+    SpectralFlux,
+    /// Magnitudes weighted by their bin, emphasising percussive onsets (Masri)
+    HighFrequencyContent,
+    /// Distance from the spectrum predicted by the previous two columns' magnitude and phase (Duxbury et al.)
+    ComplexDomain,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThresholdSettings {
+    /// Number of columns either side used for the moving median, and that an onset must be the maximum over
+    pub window: usize,
+    /// Added to the moving median, relative to the largest novelty value
+    pub offset: f64,
+    /// Multiplies the moving median
+    pub multiplier: f64,
+    /// Smallest number of columns between consecutive onsets
+    pub min_gap: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub function: NoveltyFunction,
+    pub threshold: ThresholdSettings,
+}
+
+/// Half wave rectified difference between consecutive columns, the first column has no novelty
+pub fn spectral_flux<S: Float, C: AsRef<[S]>>(columns: &[C]) -> Vec<S> {
+    let mut novelty = Vec::with_capacity(columns.len());
+
+    novelty.extend(columns.first().map(|_| S::zero()));
+    novelty.extend(columns.windows(2).map(|pair| {
+        pair[1]
+            .as_ref()
+            .iter()
+            .zip(pair[0].as_ref())
+            .fold(S::zero(), |acc, (current, previous)| {
+                acc + (*current - *previous).max(S::zero())
+            })
+    }));
+
+    novelty
+}
+
+/// Squared magnitudes weighted by their bin index
+pub fn high_frequency_content<S: Float, C: AsRef<[S]>>(columns: &[C]) -> Vec<S> {
+    columns
+        .iter()
+        .map(|column| {
+            column
+                .as_ref()
+                .iter()
+                .enumerate()
+                .fold(S::zero(), |acc, (bin, magnitude)| {
+                    acc + S::from(bin).unwrap() * *magnitude * *magnitude
+                })
+        })
+        .collect()
+}
+
+/// Picks onsets from `novelty` using a moving median threshold, returning the indexes of the onset columns.
+///
+/// The novelty is scaled so its largest value is 1 before thresholding.
+pub fn pick_onsets<S: Float>(novelty: &[S], settings: &ThresholdSettings) -> Vec<usize> {
+    let max = novelty.iter().copied().fold(S::zero(), S::max);
+    if max <= S::zero() {
+        return Vec::new();
+    }
+
+    let mut onsets: Vec<usize> = Vec::new();
+    let mut neighbourhood = Vec::with_capacity(2 * settings.window + 1);
+
+    for (index, value) in novelty.iter().enumerate() {
+        let value = (*value / max).to_f64().unwrap();
+        let first = index.saturating_sub(settings.window);
+        let last = (index + settings.window).min(novelty.len() - 1);
+
+        neighbourhood.clear();
+        neighbourhood.extend(
+            novelty[first..=last]
+                .iter()
+                .map(|value| (*value / max).to_f64().unwrap()),
+        );
+
+        if neighbourhood.iter().any(|other| *other > value) {
+            continue;
+        }
+
+        neighbourhood.sort_by(|l, r| l.partial_cmp(r).unwrap_or(core::cmp::Ordering::Less));
+        let median = neighbourhood[neighbourhood.len() / 2];
+
+        if value <= settings.offset + settings.multiplier * median {
+            continue;
+        }
+
+        if onsets
+            .last()
+            .is_some_and(|last| index - last < settings.min_gap.max(1))
+        {
+            continue;
+        }
+
+        onsets.push(index);
+    }
+
+    onsets
+}
+
+/// Finds onsets in samples, framing them the same way as [`SpectrogramProcessor`]
+pub struct OnsetDetector<S: Float> {
+    settings: Settings,
+    processor: SpectrogramProcessor<S>,
+}
+
+impl<S: Float> OnsetDetector<S> {
+    pub fn new(
+        spectrogram_settings: spectrogram::Settings,
+        settings: Settings,
+        generator: &Generator<S>,
+    ) -> Self {
+        Self {
+            settings,
+            processor: SpectrogramProcessor::new(spectrogram_settings, generator),
+        }
+    }
+
+    /// The novelty of every column of `samples`
+    pub fn novelty(&mut self, samples: &[S]) -> Vec<S> {
+        let frames = self.processor.frames(samples.len());
+        let bins = self.processor.bins();
+        let mut scratch = Vec::new();
+
+        match self.settings.function {
+            NoveltyFunction::SpectralFlux | NoveltyFunction::HighFrequencyContent => {
+                let mut columns = vec![S::zero(); frames * bins];
+                for (index, column) in columns.chunks_exact_mut(bins.max(1)).enumerate() {
+                    let frame = self.processor.frame(samples, index, &mut scratch);
+                    self.processor.process_frame(frame, column);
+                }
+
+                let columns = columns.chunks_exact(bins.max(1)).collect::<Vec<_>>();
+                match self.settings.function {
+                    NoveltyFunction::SpectralFlux => spectral_flux(&columns),
+                    _ => high_frequency_content(&columns),
+                }
+            }
+            NoveltyFunction::ComplexDomain => {
+                let mut previous = vec![Complex::new(S::zero(), S::zero()); bins];
+                let mut before_previous = previous.clone();
+
+                (0..frames)
+                    .map(|index| {
+                        let frame = self.processor.frame(samples, index, &mut scratch);
+                        let spectrum = self.processor.process_frame_complex(frame);
+
+                        let novelty = spectrum
+                            .iter()
+                            .zip(previous.iter().zip(before_previous.iter()))
+                            .fold(S::zero(), |acc, (current, (previous, before))| {
+                                let phase = S::from(2.0).unwrap() * previous.arg() - before.arg();
+                                let predicted = Complex::from_polar(previous.norm(), phase);
+                                acc + (*current - predicted).norm()
+                            });
+
+                        before_previous.copy_from_slice(&previous);
+                        previous.copy_from_slice(spectrum);
+
+                        // the first two columns have nothing to be predicted from
+                        if index < 2 {
+                            S::zero()
+                        } else {
+                            novelty
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Indexes of the columns of `samples` that onsets occur in
+    pub fn onsets(&mut self, samples: &[S]) -> Vec<usize> {
+        let novelty = self.novelty(samples);
+        pick_onsets(&novelty, &self.settings.threshold)
+    }
+
+    /// Times in seconds of the onsets in `samples`, measured from the start of the column they occur in
+    /// (or its centre when the spectrogram is centred)
+    pub fn onset_times(&mut self, samples: &[S], sample_rate: usize) -> Vec<f64> {
+        let hop_len = self.processor.hop_len();
+
+        self.onsets(samples)
+            .into_iter()
+            .map(|frame| (frame * hop_len) as f64 / sample_rate as f64)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::WindowFunction;
+
+    const STEP: usize = 4096;
+
+    /// A sine centred on an fft bin, so its magnitudes and phase advance are steady, that gets much louder at
+    /// [`STEP`]
+    fn step() -> Vec<f32> {
+        (0..2 * STEP)
+            .map(|n| {
+                let amplitude = if n < STEP { 0.05 } else { 1.0 };
+                let phase = core::f32::consts::TAU * 32.0 * n as f32 / 512.0;
+                amplitude * num_traits::Float::sin(phase)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_step_in_amplitude_is_one_onset() {
+        let spectrogram_settings = spectrogram::Settings {
+            fft_len: 512,
+            window_len: 512,
+            fft_overlap: 256,
+            window_function: WindowFunction::Hann,
+            center: false,
+        };
+
+        for function in [
+            NoveltyFunction::SpectralFlux,
+            NoveltyFunction::ComplexDomain,
+        ] {
+            let mut detector = OnsetDetector::new(
+                spectrogram_settings.clone(),
+                Settings {
+                    function,
+                    threshold: ThresholdSettings {
+                        window: 3,
+                        offset: 0.1,
+                        multiplier: 1.0,
+                        min_gap: 2,
+                    },
+                },
+                &Generator::new(),
+            );
+
+            let onsets = detector.onsets(&step());
+            assert_eq!(onsets.len(), 1, "{function:?}: {onsets:?}");
+
+            let start = onsets[0] * spectrogram_settings.hop_len();
+            assert!(
+                (start..start + spectrogram_settings.window_len).contains(&STEP),
+                "{function:?}: {onsets:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use alloc::{vec, vec::Vec};
+
+use crate::spectrogram::Float;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Number of columns either side that a peak must be the loudest over
+    pub time_radius: usize,
+    /// Number of bins either side that a peak must be the loudest over
+    pub frequency_radius: usize,
+    /// Peaks at or below this magnitude are ignored
+    pub threshold: f64,
+    /// Number of equally sized frequency bands each column is split into when limiting density
+    pub n_bands: usize,
+    /// Maximum number of peaks kept in each band of a column, the loudest are kept
+    pub max_peaks_per_band: usize,
+}
+
+/// A local maximum in a spectrogram
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak<S: Float> {
+    /// Index of the column the peak is in
+    pub frame: usize,
+    pub bin: usize,
+    pub magnitude: S,
+}
+
+/// Finds the constellation of local maxima in `columns`, ordered by frame and then by bin.
+///
+/// Every column must be the same length.
+pub fn find_peaks<S: Float, C: AsRef<[S]>>(columns: &[C], settings: &Settings) -> Vec<Peak<S>> {
+    let Some(height) = columns.first().map(|column| column.as_ref().len()) else {
+        return Vec::new();
+    };
+
+    // the maximum over each neighbourhood is separable, so take it over frequency then over time
+    let frequency_max = columns
+        .iter()
+        .map(|column| sliding_max(column.as_ref(), settings.frequency_radius))
+        .collect::<Vec<_>>();
+
+    let mut neighbourhood_max = vec![S::neg_infinity(); height];
+    let threshold = S::from(settings.threshold).unwrap();
+    let band_size = height.div_ceil(settings.n_bands.max(1)).max(1);
+
+    let mut peaks = Vec::new();
+    let mut column_peaks = Vec::new();
+
+    for (frame, column) in columns.iter().enumerate() {
+        let column = column.as_ref();
+        assert_eq!(column.len(), height, "every column must be the same length");
+
+        let first = frame.saturating_sub(settings.time_radius);
+        let last = (frame + settings.time_radius).min(columns.len() - 1);
+
+        neighbourhood_max.fill(S::neg_infinity());
+        for maxes in &frequency_max[first..=last] {
+            for (max, value) in neighbourhood_max.iter_mut().zip(maxes.iter()) {
+                *max = max.max(*value);
+            }
+        }
+
+        for band_start in (0..height).step_by(band_size) {
+            let band_end = (band_start + band_size).min(height);
+
+            column_peaks.clear();
+            column_peaks.extend(
+                (band_start..band_end)
+                    .filter(|bin| {
+                        column[*bin] > threshold && column[*bin] >= neighbourhood_max[*bin]
+                    })
+                    .map(|bin| Peak {
+                        frame,
+                        bin,
+                        magnitude: column[bin],
+                    }),
+            );
+
+            if column_peaks.len() > settings.max_peaks_per_band {
+                column_peaks.sort_by(|l, r| {
+                    r.magnitude
+                        .partial_cmp(&l.magnitude)
+                        .unwrap_or(core::cmp::Ordering::Less)
+                });
+                column_peaks.truncate(settings.max_peaks_per_band);
+                column_peaks.sort_by_key(|peak| peak.bin);
+            }
+
+            peaks.extend_from_slice(&column_peaks);
+        }
+    }
+
+    peaks
+}
+
+/// Maximum of each value and the `radius` values either side of it
+fn sliding_max<S: Float>(values: &[S], radius: usize) -> Vec<S> {
+    (0..values.len())
+        .map(|index| {
+            let first = index.saturating_sub(radius);
+            let last = (index + radius).min(values.len() - 1);
+
+            values[first..=last]
+                .iter()
+                .copied()
+                .fold(S::neg_infinity(), S::max)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(n_bands: usize, max_peaks_per_band: usize) -> Settings {
+        Settings {
+            time_radius: 2,
+            frequency_radius: 2,
+            threshold: 0.0,
+            n_bands,
+            max_peaks_per_band,
+        }
+    }
+
+    #[test]
+    fn finds_a_single_local_maximum() {
+        let mut columns = vec![vec![0.0f32; 16]; 8];
+        columns[3][5] = 1.0;
+        // quieter values within the time and frequency radius of the peak aren't peaks themselves
+        columns[4][6] = 0.5;
+        columns[2][4] = 0.5;
+
+        assert_eq!(
+            find_peaks(&columns, &settings(1, 10)),
+            vec![Peak {
+                frame: 3,
+                bin: 5,
+                magnitude: 1.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn band_limiting_keeps_the_loudest() {
+        let mut column = vec![0.0f32; 16];
+        for (bin, magnitude) in [(1, 0.2), (4, 0.9), (7, 0.5), (10, 0.3), (13, 0.8)] {
+            column[bin] = magnitude;
+        }
+
+        let peaks = find_peaks(&[column], &settings(2, 1));
+        assert_eq!(
+            peaks.iter().map(|peak| peak.bin).collect::<Vec<_>>(),
+            vec![4, 13]
+        );
+    }
+}
@@ -1,55 +1,195 @@
-use std::{
-    collections::{HashMap, VecDeque},
-    sync::{Arc, Mutex, RwLock},
-};
+use alloc::{collections::VecDeque, sync::Arc, vec, vec::Vec};
 
 use num_complex::Complex;
-use num_traits::Zero;
-use rustfft::{Fft, FftPlanner};
 use tracing::instrument;
 
-pub trait Float: rustfft::FftNum + num_traits::Float {}
+use crate::{
+    fft::{self, ComplexToReal, RealToComplex},
+    window::WindowFunction,
+};
+
+#[cfg(feature = "std")]
+pub trait Float: realfft::FftNum + num_traits::Float {}
+#[cfg(not(feature = "std"))]
+pub trait Float:
+    num_traits::Float
+    + num_traits::FromPrimitive
+    + num_traits::Signed
+    + core::fmt::Debug
+    + Send
+    + Sync
+    + 'static
+{
+}
 impl Float for f64 {}
 impl Float for f32 {}
 
+type WindowKey = (WindowFunction, usize);
+
+/// Values that are created at most once per key, without holding a lock while they are created, so
+/// creating one value never blocks lookups or creation of any other
+struct OnceMap<K, V> {
+    #[cfg(feature = "std")]
+    slots: std::sync::RwLock<std::collections::HashMap<K, Arc<std::sync::OnceLock<V>>>>,
+    /// Without std there's no hashing, but only a handful of keys are ever used
+    #[cfg(not(feature = "std"))]
+    slots: spin::RwLock<Vec<(K, Arc<spin::Once<V>>)>>,
+}
+
+#[cfg(feature = "std")]
+impl<K: Eq + core::hash::Hash, V: Clone> OnceMap<K, V> {
+    fn get_or_init(&self, key: K, init: impl FnOnce() -> V) -> V {
+        let slot = self.slots.read().unwrap().get(&key).cloned();
+        let slot =
+            slot.unwrap_or_else(|| self.slots.write().unwrap().entry(key).or_default().clone());
+
+        slot.get_or_init(init).clone()
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<K: PartialEq, V: Clone> OnceMap<K, V> {
+    fn get_or_init(&self, key: K, init: impl FnOnce() -> V) -> V {
+        let slot = find(&self.slots.read(), &key);
+        let slot = slot.unwrap_or_else(|| {
+            let mut slots = self.slots.write();
+            find(&slots, &key).unwrap_or_else(|| {
+                let slot = Arc::new(spin::Once::new());
+                slots.push((key, slot.clone()));
+                slot
+            })
+        });
+
+        slot.call_once(init).clone()
+    }
+}
+
+impl<K, V> Default for OnceMap<K, V> {
+    fn default() -> Self {
+        Self {
+            slots: Default::default(),
+        }
+    }
+}
+
+/// Plans and windows made before the generator is shared, which never change afterwards.
+///
+/// There are only ever a handful of each, so they're found by searching through them.
+#[derive(Clone)]
+struct Prepared<T: Float> {
+    forward_ffts: Vec<(usize, Arc<dyn RealToComplex<T>>)>,
+    inverse_ffts: Vec<(usize, Arc<dyn ComplexToReal<T>>)>,
+    windows: Vec<(WindowKey, Arc<Vec<T>>)>,
+}
+
+// a derived Default would needlessly require `T: Default`
+impl<T: Float> Default for Prepared<T> {
+    fn default() -> Self {
+        Self {
+            forward_ffts: Vec::new(),
+            inverse_ffts: Vec::new(),
+            windows: Vec::new(),
+        }
+    }
+}
+
+fn find<K: PartialEq, V: Clone>(entries: &[(K, V)], key: &K) -> Option<V> {
+    entries
+        .iter()
+        .find(|(other, _)| other == key)
+        .map(|(_, value)| value.clone())
+}
+
+fn insert<K: PartialEq, V>(entries: &mut Vec<(K, V)>, key: K, value: V) {
+    match entries.iter_mut().find(|(other, _)| *other == key) {
+        Some((_, existing)) => *existing = value,
+        None => entries.push((key, value)),
+    }
+}
+
+/// Shares fft plans and windows between processors, including across threads.
+///
+/// Anything added with the `with_*` methods is made up front and looked up without any locking, so
+/// prepare a generator before cloning it into each thread. Anything else is made the first time it is
+/// needed, exactly once.
 #[derive(Clone)]
 pub struct Generator<T: Float> {
-    fft_planner: Arc<Mutex<FftPlanner<T>>>,
-    hanns: Arc<RwLock<HashMap<usize, Arc<Vec<T>>>>>,
+    prepared: Arc<Prepared<T>>,
+    forward_ffts: Arc<OnceMap<usize, Arc<dyn RealToComplex<T>>>>,
+    inverse_ffts: Arc<OnceMap<usize, Arc<dyn ComplexToReal<T>>>>,
+    windows: Arc<OnceMap<WindowKey, Arc<Vec<T>>>>,
 }
 
 impl<T: Float> Generator<T> {
     pub fn new() -> Self {
         Self {
-            fft_planner: Arc::new(Mutex::new(rustfft::FftPlanner::new())),
-            hanns: Arc::default(),
+            prepared: Arc::default(),
+            forward_ffts: Arc::default(),
+            inverse_ffts: Arc::default(),
+            windows: Arc::default(),
         }
     }
 
-    fn get_forward_fft(&self, len: usize) -> Arc<dyn Fft<T>> {
-        let mut planner = self.fft_planner.lock().unwrap();
+    /// Prepares the forward fft and window used by a [`SpectrogramProcessor`] with `settings`
+    pub fn with_settings(self, settings: &Settings) -> Self {
+        self.with_forward_fft(settings.fft_len)
+            .with_window(settings.window_function, settings.window_len)
+    }
 
-        planner.plan_fft_forward(len)
+    pub fn with_forward_fft(mut self, len: usize) -> Self {
+        let fft = fft::plan_forward(len);
+        insert(&mut self.prepared_mut().forward_ffts, len, fft);
+        self
     }
 
-    fn get_hann(&self, size: usize) -> Arc<Vec<T>> {
-        let read = self.hanns.read().unwrap();
+    pub fn with_inverse_fft(mut self, len: usize) -> Self {
+        let fft = fft::plan_inverse(len);
+        insert(&mut self.prepared_mut().inverse_ffts, len, fft);
+        self
+    }
 
-        if read.contains_key(&size) {
-            read.get(&size).unwrap().to_owned()
-        } else {
-            drop(read);
-            self.generate_hann(size)
+    pub fn with_window(mut self, window_function: WindowFunction, size: usize) -> Self {
+        let window = generate_window(window_function, size);
+        insert(
+            &mut self.prepared_mut().windows,
+            (window_function, size),
+            window,
+        );
+        self
+    }
+
+    /// Copies the prepared values if this generator has already been cloned, so clones never see them change
+    fn prepared_mut(&mut self) -> &mut Prepared<T> {
+        Arc::make_mut(&mut self.prepared)
+    }
+
+    pub(crate) fn get_forward_fft(&self, len: usize) -> Arc<dyn RealToComplex<T>> {
+        match find(&self.prepared.forward_ffts, &len) {
+            Some(fft) => fft,
+            None => self
+                .forward_ffts
+                .get_or_init(len, || fft::plan_forward(len)),
+        }
+    }
+
+    pub(crate) fn get_inverse_fft(&self, len: usize) -> Arc<dyn ComplexToReal<T>> {
+        match find(&self.prepared.inverse_ffts, &len) {
+            Some(fft) => fft,
+            None => self
+                .inverse_ffts
+                .get_or_init(len, || fft::plan_inverse(len)),
         }
     }
 
-    #[instrument(skip(self), level = "trace")]
-    fn generate_hann(&self, size: usize) -> Arc<Vec<T>> {
-        let hann = generate_hanning_window(size);
-        let hann = Arc::new(hann);
-        let mut write = self.hanns.write().unwrap();
-        write.insert(size, hann.clone());
-        hann
+    pub(crate) fn get_window(&self, window_function: WindowFunction, size: usize) -> Arc<Vec<T>> {
+        let key = (window_function, size);
+
+        match find(&self.prepared.windows, &key) {
+            Some(window) => window,
+            None => self
+                .windows
+                .get_or_init(key, || generate_window(window_function, size)),
+        }
     }
 }
 
@@ -59,17 +199,9 @@ impl<T: Float> Default for Generator<T> {
     }
 }
 
-fn generate_hanning_window<T: Float>(size: usize) -> Vec<T> {
-    let half = T::from(0.5).unwrap();
-    let tau = T::from(std::f64::consts::TAU).unwrap();
-
-    let mut out = vec![T::zero(); size];
-
-    for (i, item) in out.iter_mut().enumerate() {
-        *item = half * (T::one() - (tau * (T::from(i).unwrap() / T::from(size).unwrap())).cos());
-    }
-
-    out
+#[instrument(level = "trace")]
+fn generate_window<T: Float>(window_function: WindowFunction, size: usize) -> Arc<Vec<T>> {
+    Arc::new(window_function.generate(size))
 }
 
 pub fn get_frequency_for_bin(bin: usize, sample_rate: usize, fft_len: usize) -> f64 {
@@ -83,90 +215,399 @@ pub fn get_bin_for_frequency(frequency: f64, sample_rate: usize, fft_len: usize)
 #[derive(Debug, Clone)]
 pub struct Settings {
     pub fft_len: usize,
+    /// Number of samples in each frame, zero padded up to `fft_len`
+    pub window_len: usize,
+    /// Number of samples each frame overlaps with the previous frame
     pub fft_overlap: usize,
+    pub window_function: WindowFunction,
+    /// Pad half a window of zeros before the first sample, so frame `i` is centred on sample `i * hop_len`
+    pub center: bool,
 }
 
-pub struct SpectrogramIterator<S: Float, T: Iterator<Item = S>> {
-    buffer: VecDeque<S>,
-    fft_scratch: Vec<Complex<S>>,
-    inner: T,
+impl Settings {
+    /// Number of samples between the starts of consecutive fft windows
+    pub fn hop_len(&self) -> usize {
+        self.window_len - self.fft_overlap
+    }
+
+    /// Number of zeros placed before the first sample
+    pub fn start_padding(&self) -> usize {
+        if self.center {
+            self.window_len / 2
+        } else {
+            0
+        }
+    }
+}
+
+/// Turns frames of `frame_len` samples, taken every `hop_len` samples, into columns of `bins` values
+pub trait FrameProcessor<S: Float> {
+    fn frame_len(&self) -> usize;
+
+    fn hop_len(&self) -> usize;
+
+    /// Number of values in each column
+    fn bins(&self) -> usize;
+
+    /// Computes a single column from `frame`, which is zero padded if shorter than [`Self::frame_len`].
+    ///
+    /// `out` must be exactly [`Self::bins`] long.
+    fn process_frame(&mut self, frame: &[S], out: &mut [S]);
+
+    /// Number of zeros treated as coming before the first sample, must be shorter than [`Self::frame_len`]
+    fn start_padding(&self) -> usize {
+        0
+    }
+
+    /// Number of columns that will be produced from `n_samples` samples
+    fn frames(&self, n_samples: usize) -> usize {
+        if n_samples == 0 {
+            return 0;
+        }
+
+        // when hops are longer than frames, the samples in between are never part of a frame
+        1 + (n_samples + self.start_padding())
+            .saturating_sub(self.frame_len().max(self.hop_len()))
+            .div_ceil(self.hop_len())
+    }
+
+    /// The samples of frame `index`, which is shorter than [`Self::frame_len`] at the end of `samples`.
+    ///
+    /// Frames that overlap the start padding are copied into `scratch` behind the padding zeros.
+    fn frame<'a>(&self, samples: &'a [S], index: usize, scratch: &'a mut Vec<S>) -> &'a [S] {
+        let padding = self.start_padding();
+        let start = index * self.hop_len();
+        let end = (start + self.frame_len()).min(samples.len() + padding);
+
+        if start >= padding {
+            &samples[start - padding..end - padding]
+        } else {
+            scratch.clear();
+            scratch.resize(padding - start, S::zero());
+            scratch.extend_from_slice(&samples[..end - padding]);
+            scratch
+        }
+    }
+
+    /// Computes every column of `samples` into `out`, which is laid out as `[frames × bins]`.
+    ///
+    /// `out` must be exactly `self.frames(samples.len()) * self.bins()` long.
+    fn process(&mut self, samples: &[S], out: &mut [S]) {
+        let bins = self.bins();
+        let frames = self.frames(samples.len());
+        assert_eq!(out.len(), frames * bins);
+
+        let mut scratch = Vec::new();
+
+        for (frame_index, column) in out.chunks_exact_mut(bins).enumerate() {
+            let frame = self.frame(samples, frame_index, &mut scratch);
+            self.process_frame(frame, column);
+        }
+    }
+
+    /// Like [`Self::process`], but resizes `out` to fit, reusing its allocation where possible
+    fn process_into_vec(&mut self, samples: &[S], out: &mut Vec<S>) {
+        out.resize(self.frames(samples.len()) * self.bins(), S::zero());
+
+        self.process(samples, out);
+    }
+}
+
+/// Computes spectrogram columns from sample slices into caller provided buffers.
+///
+/// All scratch space is allocated once up front, so processing does not allocate per column.
+pub struct SpectrogramProcessor<S: Float> {
     settings: Settings,
-    hann: Vec<S>,
-    fft: Arc<dyn Fft<S>>,
+    window: Arc<Vec<S>>,
+    normalisation: S,
+    fft: Arc<dyn RealToComplex<S>>,
+    fft_input: Vec<S>,
+    fft_output: Vec<Complex<S>>,
+    fft_scratch: Vec<Complex<S>>,
 }
 
-impl<S: Float, T: Iterator<Item = S>> SpectrogramIterator<S, T> {
-    pub fn new(wraps: T, settings: Settings, generator: &Generator<S>) -> Self {
+impl<S: Float> SpectrogramProcessor<S> {
+    pub fn new(settings: Settings, generator: &Generator<S>) -> Self {
+        assert!(
+            settings.window_len <= settings.fft_len,
+            "window length ({}) must not be longer than the fft length ({})",
+            settings.window_len,
+            settings.fft_len
+        );
+        assert!(
+            settings.fft_overlap < settings.window_len,
+            "fft overlap ({}) must be smaller than the window length ({})",
+            settings.fft_overlap,
+            settings.window_len
+        );
+
         let fft = generator.get_forward_fft(settings.fft_len);
-        let hann = generator.get_hann(settings.fft_len).to_vec();
+        let window = generator.get_window(settings.window_function, settings.window_len);
 
         Self {
-            buffer: VecDeque::with_capacity(settings.fft_len),
-            fft_scratch: vec![Complex::zero(); settings.fft_len],
-            inner: wraps,
+            normalisation: S::from(window.len()).unwrap().sqrt(),
+            fft_input: fft.make_input_vec(),
+            fft_output: fft.make_output_vec(),
+            fft_scratch: fft.make_scratch_vec(),
             settings,
-            hann,
+            window,
             fft,
         }
     }
 
-    fn generate_spectrogram_col(
-        &mut self,
-        samples: impl IntoIterator<Item = Complex<S>>,
-    ) -> Vec<S> {
-        let mut hanned = samples
-            .into_iter()
-            .zip(self.hann.iter())
-            .map(|(sample, hann)| sample * *hann)
-            .collect::<Vec<Complex<S>>>();
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Computes the complex spectrum of a frame, [`FrameProcessor::bins`] long and scaled like the magnitudes
+    pub fn process_frame_complex(&mut self, frame: &[S]) -> &[Complex<S>] {
+        assert!(frame.len() <= self.settings.window_len);
+
+        // anything past the window is zero padding
+        self.fft_input.fill(S::zero());
+        for (index, (input, weight)) in self
+            .fft_input
+            .iter_mut()
+            .zip(self.window.iter())
+            .enumerate()
+        {
+            *input = frame.get(index).copied().unwrap_or(S::zero()) * *weight;
+        }
+
         self.fft
-            .process_with_scratch(&mut hanned, &mut self.fft_scratch);
-        hanned
-            .into_iter()
-            .take(self.settings.fft_len / 2)
-            .map(num_complex::Complex::norm)
-            .map(|v| v / S::from(self.hann.len()).unwrap().sqrt())
-            .collect::<Vec<_>>()
+            .process_with_scratch(
+                &mut self.fft_input,
+                &mut self.fft_output,
+                &mut self.fft_scratch,
+            )
+            .expect("fft buffers should always have the planned lengths");
+
+        let bins = self.bins();
+        for bin in self.fft_output[..bins].iter_mut() {
+            *bin = *bin / self.normalisation;
+        }
+
+        &self.fft_output[..bins]
+    }
+}
+
+impl<S: Float> FrameProcessor<S> for SpectrogramProcessor<S> {
+    fn frame_len(&self) -> usize {
+        self.settings.window_len
+    }
+
+    fn hop_len(&self) -> usize {
+        self.settings.hop_len()
+    }
+
+    fn start_padding(&self) -> usize {
+        self.settings.start_padding()
+    }
+
+    fn bins(&self) -> usize {
+        self.settings.fft_len / 2
+    }
+
+    fn process_frame(&mut self, frame: &[S], out: &mut [S]) {
+        assert_eq!(out.len(), self.bins());
+
+        for (value, bin) in out.iter_mut().zip(self.process_frame_complex(frame)) {
+            *value = bin.norm();
+        }
+    }
+}
+
+/// Pulls samples from an iterator into overlapping frames, yielding a column for each
+pub struct FrameIterator<S: Float, T: Iterator<Item = S>, P: FrameProcessor<S>> {
+    buffer: VecDeque<S>,
+    inner: T,
+    inner_finished: bool,
+    fresh_samples: usize,
+    /// Samples to discard before the next frame starts, when hops are longer than frames
+    skip_samples: usize,
+    processor: P,
+}
+
+impl<S: Float, T: Iterator<Item = S>, P: FrameProcessor<S>> FrameIterator<S, T, P> {
+    pub fn with_processor(wraps: T, processor: P) -> Self {
+        let mut buffer = VecDeque::with_capacity(processor.frame_len());
+        // the padding is never fresh, so it alone can't produce a frame
+        buffer.resize(processor.start_padding(), S::zero());
+
+        Self {
+            buffer,
+            inner: wraps,
+            inner_finished: false,
+            fresh_samples: 0,
+            skip_samples: 0,
+            processor,
+        }
+    }
+
+    pub fn processor(&self) -> &P {
+        &self.processor
+    }
+}
+
+pub type SpectrogramIterator<S, T> = FrameIterator<S, T, SpectrogramProcessor<S>>;
+
+impl<S: Float, T: Iterator<Item = S>> SpectrogramIterator<S, T> {
+    pub fn new(wraps: T, settings: Settings, generator: &Generator<S>) -> Self {
+        Self::with_processor(wraps, SpectrogramProcessor::new(settings, generator))
     }
 }
 
-impl<S: Float, T: Iterator<Item = S>> Iterator for SpectrogramIterator<S, T> {
+impl<S: Float, T: Iterator<Item = S>, P: FrameProcessor<S>> Iterator for FrameIterator<S, T, P> {
     type Item = Vec<S>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let next_sample = self.inner.next();
+        let frame_len = self.processor.frame_len();
+        let hop_len = self.processor.hop_len();
+
+        while self.buffer.len() < frame_len {
+            let next_sample = if self.inner_finished {
+                None
+            } else {
+                self.inner.next()
+            };
 
             match next_sample {
-                Some(sample) => self.buffer.push_back(sample),
+                Some(_) if self.skip_samples > 0 => self.skip_samples -= 1,
+                Some(sample) => {
+                    self.buffer.push_back(sample);
+                    self.fresh_samples += 1;
+                }
                 None => {
-                    if self.buffer.is_empty() {
+                    self.inner_finished = true;
+
+                    // every sample left in the buffer has already been part of a previous window
+                    if self.fresh_samples == 0 {
                         return None;
-                    } else {
-                        self.buffer.resize(self.settings.fft_len, S::zero())
                     }
+
+                    self.buffer.resize(frame_len, S::zero())
                 }
             };
+        }
+
+        assert!(self.buffer.len() <= frame_len);
 
-            if self.buffer.len() >= self.settings.fft_len {
-                break;
+        let mut column = vec![S::zero(); self.processor.bins()];
+        self.processor
+            .process_frame(self.buffer.make_contiguous(), &mut column);
+
+        let drained = hop_len.min(frame_len);
+        self.buffer.drain(..drained).for_each(drop);
+        self.skip_samples = hop_len - drained;
+        self.fresh_samples = 0;
+
+        Some(column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fft::ComplexFft;
+
+    #[test]
+    fn frames_start_every_hop() {
+        const LEN: usize = 16;
+        const HOP: usize = 4;
+        const N_SAMPLES: usize = 42;
+
+        let settings = Settings {
+            fft_len: LEN,
+            window_len: LEN,
+            fft_overlap: LEN - HOP,
+            window_function: WindowFunction::Hann,
+            center: false,
+        };
+        let generator = Generator::<f64>::new();
+
+        // an impulse only shows up in the columns of the frames it falls in
+        for impulse in 0..N_SAMPLES {
+            let samples = (0..N_SAMPLES).map(|n| if n == impulse { 1.0 } else { 0.0 });
+            let columns =
+                SpectrogramIterator::new(samples, settings.clone(), &generator).collect::<Vec<_>>();
+
+            // the last frame is cut short by the end of the samples, and zero padded
+            assert_eq!(columns.len(), 1 + (N_SAMPLES - LEN).div_ceil(HOP));
+
+            for (index, column) in columns.iter().enumerate() {
+                let energy = column.iter().map(|bin| bin * bin).sum::<f64>();
+                let offset = impulse as isize - (index * HOP) as isize;
+
+                // the hann window is zero at its first sample
+                if (1..LEN as isize - 1).contains(&offset) {
+                    assert!(
+                        energy > 1e-6,
+                        "sample {impulse} is missing from frame {index}"
+                    );
+                } else if !(0..LEN as isize).contains(&offset) {
+                    assert_eq!(energy, 0.0, "sample {impulse} is in frame {index}");
+                }
             }
         }
+    }
 
-        assert!(self.buffer.len() <= self.settings.fft_len);
+    #[test]
+    fn real_fft_columns_match_complex_fft() {
+        for (fft_len, window_len) in [(999, 999), (1024, 700)] {
+            let settings = Settings {
+                fft_len,
+                window_len,
+                fft_overlap: 0,
+                window_function: WindowFunction::Hann,
+                center: false,
+            };
+            let generator = Generator::<f64>::new();
+            let mut processor = SpectrogramProcessor::new(settings, &generator);
 
-        let samples = self
-            .buffer
-            .iter()
-            .take(self.settings.fft_len)
-            .copied()
-            .map(|s| Complex::new(s, S::zero()))
-            .collect::<Vec<_>>();
+            let frame = (0..window_len)
+                .map(|n| num_traits::Float::sin(n as f64 * 0.3) + (n % 7) as f64 * 0.05)
+                .collect::<Vec<_>>();
+            let mut column = vec![0.0; processor.bins()];
+            processor.process_frame(&frame, &mut column);
 
-        self.buffer.drain(..self.settings.fft_len).for_each(drop);
+            let window = generator.get_window(WindowFunction::Hann, window_len);
+            let mut expected = vec![Complex::new(0.0, 0.0); fft_len];
+            for ((expected, sample), weight) in expected.iter_mut().zip(&frame).zip(window.iter()) {
+                *expected = Complex::new(sample * weight, 0.0);
+            }
+            ComplexFft::new(fft_len).process(&mut expected);
+
+            let normalisation = num_traits::Float::sqrt(window_len as f64);
+            for (bin, (actual, expected)) in column.iter().zip(&expected).enumerate() {
+                let expected = expected.norm() / normalisation;
+                assert!(
+                    (actual - expected).abs() < 1e-9 * expected.max(1.0),
+                    "fft length {fft_len}, bin {bin}: {actual} != {expected}"
+                );
+            }
+        }
+    }
 
-        let spectrogram = self.generate_spectrogram_col(samples);
+    #[test]
+    fn generator_makes_each_value_once() {
+        let generator = Generator::<f32>::new();
+        let clone = generator.clone();
 
-        Some(spectrogram)
+        assert!(Arc::ptr_eq(
+            &generator.get_forward_fft(100),
+            &clone.get_forward_fft(100)
+        ));
+        assert!(Arc::ptr_eq(
+            &generator.get_inverse_fft(64),
+            &clone.get_inverse_fft(64)
+        ));
+        assert!(Arc::ptr_eq(
+            &generator.get_window(WindowFunction::Hann, 64),
+            &clone.get_window(WindowFunction::Hann, 64)
+        ));
+        assert!(!Arc::ptr_eq(
+            &generator.get_window(WindowFunction::Hann, 64),
+            &clone.get_window(WindowFunction::Hann, 128)
+        ));
     }
 }
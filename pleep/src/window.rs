@@ -0,0 +1,256 @@
+use alloc::{vec, vec::Vec};
+
+#[cfg(not(feature = "std"))]
+#[allow(
+    unused_imports,
+    reason = "unused when std is linked anyway, as it is for tests"
+)]
+use num_traits::Float as _;
+
+use crate::spectrogram::Float;
+
+/// Window functions that can be applied to each fft frame.
+///
+/// All windows are periodic (DFT-even), matching what is normally wanted for spectral analysis.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum WindowFunction {
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+    BlackmanHarris,
+    Kaiser {
+        beta: f32,
+    },
+    Rectangular,
+    FlatTop,
+}
+
+impl WindowFunction {
+    pub fn generate<T: Float>(&self, size: usize) -> Vec<T> {
+        match self {
+            WindowFunction::Hann => generate_cosine_sum_window(size, &[0.5, 0.5]),
+            WindowFunction::Hamming => generate_cosine_sum_window(size, &[0.54, 0.46]),
+            WindowFunction::Blackman => generate_cosine_sum_window(size, &[0.42, 0.5, 0.08]),
+            WindowFunction::BlackmanHarris => {
+                generate_cosine_sum_window(size, &[0.35875, 0.48829, 0.14128, 0.01168])
+            }
+            WindowFunction::Kaiser { beta } => generate_kaiser_window(size, *beta as f64),
+            WindowFunction::Rectangular => vec![T::one(); size],
+            WindowFunction::FlatTop => generate_cosine_sum_window(
+                size,
+                &[
+                    0.21557895,
+                    0.41663158,
+                    0.277263158,
+                    0.083578947,
+                    0.006947368,
+                ],
+            ),
+        }
+    }
+}
+
+impl PartialEq for WindowFunction {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (WindowFunction::Kaiser { beta: l }, WindowFunction::Kaiser { beta: r }) => {
+                l.to_bits() == r.to_bits()
+            }
+            _ => core::mem::discriminant(self) == core::mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for WindowFunction {}
+
+impl core::hash::Hash for WindowFunction {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        core::mem::discriminant(self).hash(state);
+
+        if let WindowFunction::Kaiser { beta } = self {
+            beta.to_bits().hash(state);
+        }
+    }
+}
+
+/// Generates `a0 - a1 cos(x) + a2 cos(2x) - a3 cos(3x) + ...`
+fn generate_cosine_sum_window<T: Float>(size: usize, coefficients: &[f64]) -> Vec<T> {
+    let tau = core::f64::consts::TAU;
+
+    (0..size)
+        .map(|i| {
+            let x = tau * i as f64 / size as f64;
+
+            let value = coefficients
+                .iter()
+                .enumerate()
+                .map(|(k, a)| {
+                    let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                    sign * a * (k as f64 * x).cos()
+                })
+                .sum::<f64>();
+
+            T::from(value).unwrap()
+        })
+        .collect()
+}
+
+fn generate_kaiser_window<T: Float>(size: usize, beta: f64) -> Vec<T> {
+    assert!(
+        beta.is_finite() && beta >= 0.0,
+        "kaiser beta must be finite and not negative, got {beta}"
+    );
+
+    let denominator = bessel_i0(beta);
+
+    (0..size)
+        .map(|i| {
+            let ratio = 2.0 * i as f64 / size as f64 - 1.0;
+            let value = bessel_i0(beta * (1.0 - ratio * ratio).max(0.0).sqrt()) / denominator;
+
+            T::from(value).unwrap()
+        })
+        .collect()
+}
+
+/// Zeroth order modified bessel function of the first kind
+fn bessel_i0(x: f64) -> f64 {
+    let half_x = x / 2.0;
+    let mut sum = 1.0;
+    let mut term = 1.0;
+
+    for k in 1..500 {
+        term *= half_x / k as f64;
+        let squared = term * term;
+        sum += squared;
+
+        if squared < sum * 1e-16 {
+            break;
+        }
+    }
+
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-8, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn cosine_sum_windows_have_known_coefficients() {
+        // the first half of each periodic window of length 8
+        let cases = [
+            (
+                WindowFunction::Hann,
+                [0.0, 0.1464466094067262, 0.5, 0.8535533905932737, 1.0],
+            ),
+            (
+                WindowFunction::Hamming,
+                [0.08, 0.2147308806541882, 0.54, 0.8652691193458119, 1.0],
+            ),
+            (
+                WindowFunction::Blackman,
+                [0.0, 0.0664466094067262, 0.34, 0.7735533905932737, 1.0],
+            ),
+            (
+                WindowFunction::BlackmanHarris,
+                [6.0e-5, 0.0217358370186796, 0.21747, 0.6957641629813204, 1.0],
+            ),
+            (
+                WindowFunction::FlatTop,
+                [
+                    -0.000421051,
+                    -0.0268721932863345,
+                    -0.05473684,
+                    0.4441353572863344,
+                    1.000000003,
+                ],
+            ),
+        ];
+
+        for (function, expected) in cases {
+            let window = function.generate::<f64>(8);
+            assert_close(&window[..5], &expected);
+        }
+    }
+
+    #[test]
+    fn windows_are_periodic() {
+        let functions = [
+            WindowFunction::Hann,
+            WindowFunction::Hamming,
+            WindowFunction::Blackman,
+            WindowFunction::BlackmanHarris,
+            WindowFunction::Kaiser { beta: 8.6 },
+            WindowFunction::Rectangular,
+            WindowFunction::FlatTop,
+        ];
+
+        for function in functions {
+            for size in [7, 8, 64] {
+                let window = function.generate::<f64>(size);
+                // a periodic window is symmetric once the first sample is set aside
+                for i in 1..size {
+                    assert!(
+                        (window[i] - window[size - i]).abs() < 1e-12,
+                        "{function:?} {size}: {window:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn kaiser_matches_reference() {
+        // `numpy.kaiser(12, 14)`, a symmetric window of 12 is the periodic window of 11
+        let expected = [
+            7.72686684e-06,
+            3.46009194e-03,
+            4.65200189e-02,
+            2.29737120e-01,
+            5.99885316e-01,
+            9.45674898e-01,
+            9.45674898e-01,
+            5.99885316e-01,
+            2.29737120e-01,
+            4.65200189e-02,
+            3.46009194e-03,
+        ];
+
+        let window = WindowFunction::Kaiser { beta: 14.0 }.generate::<f64>(11);
+        for (a, e) in window.iter().zip(expected) {
+            assert!((a - e).abs() < e * 1e-6, "{window:?}");
+        }
+    }
+
+    #[test]
+    fn bessel_i0_matches_reference() {
+        for (x, expected) in [
+            (0.0, 1.0),
+            (1.0, 1.2660658777520082),
+            (5.0, 27.239871823604442),
+        ] {
+            assert!((bessel_i0(x) - expected).abs() < expected * 1e-12);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "kaiser beta")]
+    fn kaiser_rejects_negative_beta() {
+        WindowFunction::Kaiser { beta: -1.0 }.generate::<f64>(8);
+    }
+
+    #[test]
+    #[should_panic(expected = "kaiser beta")]
+    fn kaiser_rejects_nan_beta() {
+        WindowFunction::Kaiser { beta: f32::NAN }.generate::<f64>(8);
+    }
+}